@@ -1,5 +1,4 @@
 #import bevy_pbr::{
-    mesh_functions,
     pbr_types,
     pbr_fragment::pbr_input_from_standard_material,
     pbr_functions::{alpha_discard, apply_pbr_lighting, main_pass_post_lighting_processing},
@@ -10,64 +9,63 @@
 @group(2) @binding(100) var blocks_texture: texture_2d_array<f32>;
 @group(2) @binding(101) var blocks_texture_sampler: sampler;
 
-const SIZE: f32 = 15.0;
-const THRESHOLD_A: f32 = 0.015;
-const THRESHOLD_B: f32 = 0.075;
+// Roughness, metallic and reflectance by block id
+@group(2) @binding(103) var<uniform> materials: array<vec4<f32>, 256>;
+
+// Boxes of selected blocks as inclusive min and exclusive max corner, unused boxes are empty
+const MAX_SELECTION_BOXES: u32 = 4u;
+@group(2) @binding(102) var<uniform> selection: array<vec4<i32>, 8>;
+
+fn is_selected(block: vec3<i32>) -> bool {
+    for (var i = 0u; i < MAX_SELECTION_BOXES; i++) {
+        if all(block >= selection[2u * i].xyz) && all(block < selection[2u * i + 1u].xyz) {
+            return true;
+        }
+    }
+    return false;
+}
 
 @fragment
 fn fragment(
     in: VertexOutput,
     @builtin(front_facing) is_front: bool,
 ) -> FragmentOutput {
-    // Get tag
-    let tag = mesh_functions::get_tag(in.instance_index);
-
-    // Discard faces
-    if (tag & (1u << 8u)) != 0u && in.world_normal.x < 0.0
-        || (tag & (1u << 9u)) != 0u && in.world_normal.x > 0.0
-        || (tag & (1u << 10u)) != 0u && in.world_normal.y < 0.0
-        || (tag & (1u << 11u)) != 0u && in.world_normal.y > 0.0
-        || (tag & (1u << 12u)) != 0u && in.world_normal.z < 0.0
-        || (tag & (1u << 13u)) != 0u && in.world_normal.z > 0.0
-     {
-        discard;
-    }
-
-    // Get block type and selected
-    let block = tag & 0xFF; // Lower 8 bits for block type
-    let selected = (tag & (1u << 14u)) != 0u; // 14th bit for selected
-
-    // Get texture layer based on block type and normal
+    // Texture layer and block id of the face, see the chunk mesh
     var layer = 0;
-    switch block {
-        case 1u: { layer = 0; }
-        case 2u: { layer = 1; }
-        case 3u: { layer = 2; }
-        case 4u: {
-            if in.world_normal.y > 0.0 {
-                layer = 4;
-            } else if in.world_normal.y < 0.0 {
-                layer = 0;
-            } else {
-                layer = 3;
-            }
-        }
-        case 5u: { layer = 5; }
-        case 6u: { layer = 6; }
-        case 7u: { layer = 7; }
-        case 8u: { layer = 8; }
-        default: {}
-    }
+    var block_id = 0;
+#ifdef VERTEX_UVS_B
+    layer = i32(in.uv_b.x + 0.5);
+    block_id = i32(in.uv_b.y + 0.5);
+#endif
+
+    // The block of the fragment is behind the face
+    let block = vec3<i32>(floor(in.world_position.xyz - 0.5 * in.world_normal));
+    let selected = is_selected(block);
 
-    // Color (TODO: Use selected to add effect)
+    // Color
     var pbr_input = pbr_input_from_standard_material(in, is_front);
     pbr_input.material.base_color = textureSample(blocks_texture, blocks_texture_sampler, in.uv, layer);
+    let material = materials[block_id];
+    pbr_input.material.perceptual_roughness = material.x;
+    pbr_input.material.metallic = material.y;
+    pbr_input.material.reflectance = vec3(material.z);
 
     // Output
     var out: FragmentOutput;
     out.color = apply_pbr_lighting(pbr_input);
     out.color = main_pass_post_lighting_processing(pbr_input, out.color);
 
+    // Selected blocks pulse and get an outline, UVs span one unit per block
+    if selected {
+        let edge = min(fract(in.uv), 1.0 - fract(in.uv));
+        let outline = step(min(edge.x, edge.y), 0.05);
+        let pulse = 0.15 + 0.1 * sin(globals.time * 6.0);
+        out.color = vec4(
+            mix(out.color.rgb, vec3(1.0), max(outline * 0.8, pulse)),
+            max(out.color.a, outline),
+        );
+    }
+
     let alpha_mode = pbr_input.material.flags & pbr_types::STANDARD_MATERIAL_FLAGS_ALPHA_MODE_RESERVED_BITS;
     if alpha_mode != pbr_types::STANDARD_MATERIAL_FLAGS_ALPHA_MODE_OPAQUE
         && alpha_mode != pbr_types::STANDARD_MATERIAL_FLAGS_ALPHA_MODE_MASK {
@@ -77,8 +75,3 @@ fn fragment(
 
     return out;
 }
-
-fn check(value: f32, threshold: f32) -> bool {
-    let fract = fract(value * SIZE);
-    return fract < threshold || fract > 1.0 - threshold;
-}
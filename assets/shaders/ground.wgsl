@@ -5,7 +5,6 @@
 }
 #import bevy_pbr::mesh_view_bindings::globals;
 
-const SIZE: f32 = 15.0;
 const THRESHOLD_A: f32 = 0.015;
 const THRESHOLD_B: f32 = 0.075;
 
@@ -17,11 +16,12 @@ fn fragment(
     // Input
     var pbr_input = pbr_input_from_standard_material(in, is_front);
 
-    // Grid
-    let line = (check(in.uv.x, THRESHOLD_A) && check(in.uv.y, THRESHOLD_B))
-        || (check(in.uv.y, THRESHOLD_A) && check(in.uv.x, THRESHOLD_B));
+    // Grid, one cell per block
+    let pos = in.world_position.xz;
+    let line = (check(pos.x, THRESHOLD_A) && check(pos.y, THRESHOLD_B))
+        || (check(pos.y, THRESHOLD_A) && check(pos.x, THRESHOLD_B));
     if line {
-        let effect = (sin(1.0 * globals.time + 2.0 * (in.uv.x + in.uv.y)) + 1.0) * 0.5;
+        let effect = (sin(1.0 * globals.time + 0.15 * (pos.x + pos.y)) + 1.0) * 0.5;
         let value = 0.0 + 0.01 * effect;
         pbr_input.material.base_color = vec4(value, value, value, 0.8);
     } else {
@@ -38,6 +38,6 @@ fn fragment(
 }
 
 fn check(value: f32, threshold: f32) -> bool {
-    let fract = fract(value * SIZE);
+    let fract = fract(value);
     return fract < threshold || fract > 1.0 - threshold;
 }
@@ -0,0 +1,274 @@
+use crate::{LinearRgb, rng::Rng};
+use bevy_math::prelude::*;
+use std::f32::consts::PI;
+
+/// How a surface scatters the light it doesn't reflect or refract perfectly.
+#[derive(Debug, Clone, Copy)]
+pub enum Brdf {
+    Lambert {
+        albedo: LinearRgb,
+    },
+    /// GGX specular with height-correlated Smith masking and Schlick Fresnel over Lambertian
+    /// diffuse, the model of Bevy's `StandardMaterial`.
+    Microfacet {
+        diffuse: LinearRgb,
+        /// Specular reflectance at normal incidence.
+        f0: LinearRgb,
+        /// GGX roughness, the square of the perceptual roughness.
+        alpha: f32,
+        dir_to_eye: Dir3,
+    },
+}
+
+/// Direction sampled by [`Brdf::sample`].
+#[derive(Debug, Clone, Copy)]
+pub struct BrdfSample {
+    pub direction: Dir3,
+    /// BRDF times cosine divided by the probability density of the direction.
+    pub weight: LinearRgb,
+    /// Whether the direction was sampled from the specular lobe.
+    pub is_specular: bool,
+}
+
+impl Brdf {
+    /// Microfacet BRDF of a surface seen from `dir_to_eye`, with the material parameters
+    /// converted like Bevy's PBR shader does.
+    pub fn microfacet(
+        base_color: LinearRgb,
+        roughness: f32,
+        metallic: f32,
+        reflectance: f32,
+        dir_to_eye: Dir3,
+    ) -> Self {
+        let roughness = roughness.clamp(0.089, 1.0);
+        let metallic = metallic.clamp(0.0, 1.0);
+        Self::Microfacet {
+            diffuse: base_color * (1.0 - metallic),
+            f0: LinearRgb::WHITE * (0.16 * reflectance * reflectance * (1.0 - metallic))
+                + base_color * metallic,
+            alpha: roughness * roughness,
+            dir_to_eye,
+        }
+    }
+
+    pub fn diffuse_albedo(&self) -> LinearRgb {
+        match *self {
+            Self::Lambert { albedo } => albedo,
+            Self::Microfacet { diffuse, .. } => diffuse,
+        }
+    }
+
+    /// The diffuse part of the BRDF.
+    pub fn diffuse(&self) -> Self {
+        Self::Lambert {
+            albedo: self.diffuse_albedo(),
+        }
+    }
+
+    /// Radiance reflected towards the eye per irradiance from `dir_to_light`.
+    pub fn evaluate(&self, normal: Dir3, dir_to_light: Dir3) -> LinearRgb {
+        match *self {
+            Self::Lambert { albedo } => albedo / PI,
+            Self::Microfacet {
+                diffuse,
+                f0,
+                alpha,
+                dir_to_eye,
+            } => {
+                let n_dot_v = normal.dot(*dir_to_eye);
+                let n_dot_l = normal.dot(*dir_to_light);
+                let Ok(half) = Dir3::new(*dir_to_eye + *dir_to_light) else {
+                    return diffuse / PI;
+                };
+                if n_dot_v <= 0.0 || n_dot_l <= 0.0 {
+                    return diffuse / PI;
+                }
+
+                let specular = ggx_distribution(alpha, normal.dot(*half))
+                    * smith_visibility(alpha, n_dot_v, n_dot_l)
+                    * schlick_fresnel(f0, dir_to_light.dot(*half));
+                diffuse / PI + specular
+            }
+        }
+    }
+
+    /// Sample the direction of a bounce, picking the lobe by its reflectance. `None` if the
+    /// surface absorbs the light.
+    pub fn sample(&self, normal: Dir3, rng: &mut Rng) -> Option<BrdfSample> {
+        let Self::Microfacet {
+            diffuse,
+            f0,
+            dir_to_eye,
+            ..
+        } = *self
+        else {
+            return Some(BrdfSample {
+                direction: cosine_weighted_direction(normal, rng),
+                weight: self.diffuse_albedo(),
+                is_specular: false,
+            });
+        };
+
+        let specular_weight = schlick_fresnel(f0, normal.dot(*dir_to_eye)).max_element();
+        let total_weight = specular_weight + diffuse.max_element();
+        if total_weight <= 0.0 {
+            return None;
+        }
+
+        let specular_probability = specular_weight / total_weight;
+        if rng.next_f32() < specular_probability {
+            let (direction, weight) = self.sample_specular(normal, rng)?;
+            Some(BrdfSample {
+                direction,
+                weight: weight / specular_probability,
+                is_specular: true,
+            })
+        } else {
+            Some(BrdfSample {
+                direction: cosine_weighted_direction(normal, rng),
+                weight: diffuse / (1.0 - specular_probability),
+                is_specular: false,
+            })
+        }
+    }
+
+    /// Sample the direction of a reflection from the distribution of microfacet normals, with
+    /// the specular BRDF times cosine divided by the probability density of the direction.
+    /// `None` for Lambertian surfaces and reflections below the surface.
+    pub fn sample_specular(&self, normal: Dir3, rng: &mut Rng) -> Option<(Dir3, LinearRgb)> {
+        let Self::Microfacet {
+            f0,
+            alpha,
+            dir_to_eye,
+            ..
+        } = *self
+        else {
+            return None;
+        };
+
+        let (tangent, bitangent) = normal.any_orthonormal_pair();
+        let phi = 2.0 * PI * rng.next_f32();
+        let u = rng.next_f32();
+        let cos_theta = ((1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u)).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let half = Dir3::new(
+            sin_theta * phi.cos() * tangent
+                + sin_theta * phi.sin() * bitangent
+                + cos_theta * *normal,
+        )
+        .ok()?;
+
+        let v_dot_h = dir_to_eye.dot(*half);
+        let direction = Dir3::new(2.0 * v_dot_h * *half - *dir_to_eye).ok()?;
+        let n_dot_v = normal.dot(*dir_to_eye);
+        let n_dot_l = normal.dot(*direction);
+        if v_dot_h <= 0.0 || n_dot_v <= 0.0 || n_dot_l <= 0.0 {
+            return None;
+        }
+
+        // The distribution cancels out of D * V * F * cos / (D * cos_h / (4 * v_dot_h))
+        let weight = schlick_fresnel(f0, v_dot_h)
+            * (smith_visibility(alpha, n_dot_v, n_dot_l) * 4.0 * n_dot_l * v_dot_h / cos_theta);
+        Some((direction, weight))
+    }
+}
+
+fn ggx_distribution(alpha: f32, n_dot_h: f32) -> f32 {
+    let alpha_squared = alpha * alpha;
+    let denominator = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+    alpha_squared / (PI * denominator * denominator)
+}
+
+/// Height-correlated Smith masking-shadowing divided by `4 * n_dot_v * n_dot_l`.
+fn smith_visibility(alpha: f32, n_dot_v: f32, n_dot_l: f32) -> f32 {
+    let alpha_squared = alpha * alpha;
+    let lambda_v = n_dot_l * (n_dot_v * n_dot_v * (1.0 - alpha_squared) + alpha_squared).sqrt();
+    let lambda_l = n_dot_v * (n_dot_l * n_dot_l * (1.0 - alpha_squared) + alpha_squared).sqrt();
+    0.5 / (lambda_v + lambda_l)
+}
+
+/// Schlick's approximation, with the reflectance at grazing angles reduced for very dark `f0`
+/// like Bevy does.
+fn schlick_fresnel(f0: LinearRgb, cos_angle: f32) -> LinearRgb {
+    let f90 = ((f0.red + f0.green + f0.blue) * 50.0 * 0.33).clamp(0.0, 1.0);
+    let factor = (1.0 - cos_angle.clamp(0.0, 1.0)).powi(5);
+    f0 * (1.0 - factor) + LinearRgb::WHITE * (f90 * factor)
+}
+
+fn cosine_weighted_direction(normal: Dir3, rng: &mut Rng) -> Dir3 {
+    let (tangent, bitangent) = normal.any_orthonormal_pair();
+    let phi = 2.0 * PI * rng.next_f32();
+    let r2 = rng.next_f32();
+    let r = r2.sqrt();
+    Dir3::new(r * phi.cos() * tangent + r * phi.sin() * bitangent + (1.0 - r2).sqrt() * *normal)
+        .unwrap_or(normal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// White metal, so the BRDF is only the specular lobe.
+    fn metal(roughness: f32, view_angle: f32) -> Brdf {
+        let dir_to_eye = Dir3::new(Vec3::new(view_angle.sin(), view_angle.cos(), 0.0)).unwrap();
+        Brdf::microfacet(LinearRgb::WHITE, roughness, 1.0, 0.5, dir_to_eye)
+    }
+
+    #[test]
+    fn specular_weight_is_brdf_times_cosine_over_pdf() {
+        let mut rng = Rng::new(1, 2);
+        for roughness in [0.2, 0.5, 1.0] {
+            let brdf = metal(roughness, 0.7);
+            let Brdf::Microfacet {
+                alpha, dir_to_eye, ..
+            } = brdf
+            else {
+                unreachable!();
+            };
+
+            for _ in 0..100 {
+                let Some((direction, weight)) = brdf.sample_specular(Dir3::Y, &mut rng) else {
+                    continue;
+                };
+                let half = Dir3::new(*direction + *dir_to_eye).unwrap();
+                let pdf = ggx_distribution(alpha, half.y) * half.y / (4.0 * dir_to_eye.dot(*half));
+                let expected = brdf.evaluate(Dir3::Y, direction) * (direction.y / pdf);
+
+                for (value, expected) in [
+                    (weight.red, expected.red),
+                    (weight.green, expected.green),
+                    (weight.blue, expected.blue),
+                ] {
+                    assert!(
+                        (value - expected).abs() <= 1e-3 * expected.max(1.0),
+                        "{value} != {expected} for roughness {roughness}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn specular_reflection_does_not_create_energy() {
+        let mut rng = Rng::new(3, 4);
+        for roughness in [0.0, 0.3, 0.6, 1.0] {
+            for view_angle in [0.0, 0.5, 1.0, 1.4] {
+                let brdf = metal(roughness, view_angle);
+                let count = 10_000;
+                let mut albedo = 0.0;
+                for _ in 0..count {
+                    if let Some((_, weight)) = brdf.sample_specular(Dir3::Y, &mut rng) {
+                        albedo += weight.max_element();
+                    }
+                }
+                albedo /= count as f32;
+
+                assert!(albedo <= 1.01, "albedo {albedo} for roughness {roughness}");
+                // Single scattering only loses energy on rough surfaces
+                if roughness < 0.1 {
+                    assert!(albedo > 0.95, "albedo {albedo} of a mirror");
+                }
+            }
+        }
+    }
+}
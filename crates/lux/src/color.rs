@@ -28,6 +28,15 @@ impl LinearRgb {
     pub fn new(red: f32, green: f32, blue: f32) -> Self {
         Self { red, green, blue }
     }
+
+    pub fn max_element(&self) -> f32 {
+        self.red.max(self.green).max(self.blue)
+    }
+
+    /// Relative luminance with the Rec. 709 weights.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
 }
 
 impl From<LinearRgba> for LinearRgb {
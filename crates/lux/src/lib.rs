@@ -1,34 +1,170 @@
+mod brdf;
 mod color;
+mod rng;
 
+use self::{brdf::Brdf, rng::Rng};
 use bevy_color::prelude::*;
 use bevy_math::prelude::*;
-use std::{f32::consts::PI, thread};
+use std::{
+    f32::consts::PI,
+    ops::Range,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+};
 
 pub use self::color::LinearRgb;
 
 pub trait Scene {
     fn lights(&self) -> &[Light];
+
+    /// Area lights of the [`Material::Emissive`] surfaces of the scene, sampled for direct light
+    /// like [`Scene::lights`] but only one of them per shaded point. Unlike those, rays can hit
+    /// them, so the path tracer only counts hits that direct light sampling can't account for.
+    fn emitters(&self) -> &Emitters {
+        static NO_EMITTERS: Emitters = Emitters {
+            lights: Vec::new(),
+            cumulative_power: Vec::new(),
+        };
+        &NO_EMITTERS
+    }
+
     fn cast_ray(&self, ray: Ray3d, max_distance: f32) -> Option<RayHit>;
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Light {
     Ambient {
         color: LinearRgb,
         intensity: f32,
     },
+    /// Light from a distant disk like the sun, spanning `angular_diameter` radians. Zero gives
+    /// hard shadows.
     Directional {
         direction: Dir3,
+        angular_diameter: f32,
         color: LinearRgb,
         intensity: f32,
+        /// Shadow rays per shaded point, more give smoother soft shadows. A single ray is enough
+        /// for hard shadows.
+        shadow_samples: u32,
     },
+    /// Light from a sphere of `radius` around `position`. Zero gives hard shadows.
     Point {
         position: Vec3,
+        radius: f32,
+        color: LinearRgb,
+        intensity: f32,
+        /// See [`Light::Directional`].
+        shadow_samples: u32,
+    },
+    /// Parallelogram spanned by the edges `u` and `v` from `corner`, emitting to the side `u × v`
+    /// points to. `intensity` is the emitted radiance.
+    Rect {
+        corner: Vec3,
+        u: Vec3,
+        v: Vec3,
+        color: LinearRgb,
+        intensity: f32,
+        /// See [`Light::Directional`].
+        shadow_samples: u32,
+    },
+    /// Axis-aligned box emitting from all of its sides. `intensity` is the emitted radiance.
+    Box {
+        min: Vec3,
+        max: Vec3,
         color: LinearRgb,
         intensity: f32,
+        /// See [`Light::Directional`].
+        shadow_samples: u32,
     },
 }
 
+impl Light {
+    /// Emitted power up to a constant factor, to pick emitters by. Point and directional lights
+    /// only have their intensity.
+    fn power(&self) -> f32 {
+        match *self {
+            Light::Ambient { .. } => 0.0,
+            Light::Directional {
+                color, intensity, ..
+            }
+            | Light::Point {
+                color, intensity, ..
+            } => color.luminance() * intensity,
+            Light::Rect {
+                u,
+                v,
+                color,
+                intensity,
+                ..
+            } => color.luminance() * intensity * u.cross(v).length(),
+            Light::Box {
+                min,
+                max,
+                color,
+                intensity,
+                ..
+            } => {
+                let size = max - min;
+                let area = 2.0 * (size.x * size.y + size.y * size.z + size.z * size.x);
+                color.luminance() * intensity * area
+            }
+        }
+    }
+}
+
+/// The [emitters](Scene::emitters) of a scene, with the distribution to pick one of them by
+/// power.
+#[derive(Debug, Clone, Default)]
+pub struct Emitters {
+    lights: Vec<Light>,
+    /// Sum of the powers of the lights up to and including each one.
+    cumulative_power: Vec<f32>,
+}
+
+impl Emitters {
+    pub fn new(lights: Vec<Light>) -> Self {
+        let cumulative_power = lights
+            .iter()
+            .scan(0.0, |sum, light| {
+                *sum += light.power();
+                Some(*sum)
+            })
+            .collect();
+        Self {
+            lights,
+            cumulative_power,
+        }
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// A light picked with a chance proportional to its power, and that chance. `None` if no
+    /// light emits anything.
+    fn pick(&self, rng: &mut Rng) -> Option<(&Light, f32)> {
+        let total = *self.cumulative_power.last()?;
+        if total <= 0.0 {
+            return None;
+        }
+        let target = rng.next_f32() * total;
+        let index = self
+            .cumulative_power
+            .partition_point(|&sum| sum <= target)
+            .min(self.lights.len() - 1);
+        let previous = match index {
+            0 => 0.0,
+            _ => self.cumulative_power[index - 1],
+        };
+        let probability = (self.cumulative_power[index] - previous) / total;
+        (probability > 0.0).then_some((&self.lights[index], probability))
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct RayHit {
     pub material: Material,
@@ -51,9 +187,69 @@ pub enum Material {
         index: f32,
         transparency: f32,
     },
+    /// Rough or glossy surface with the parameters of Bevy's `StandardMaterial`: GGX specular
+    /// over Lambertian diffuse, with reflections importance sampled.
+    Microfacet {
+        base_color: LinearRgb,
+        /// Perceptual roughness, from 0.089 for a mirror to 1. Lower values are clamped.
+        roughness: f32,
+        /// 0 for dielectrics, 1 for metals, which tint their reflections by the base color and
+        /// have no diffuse reflection.
+        metallic: f32,
+        /// Specular reflectance of dielectrics, 0.5 for 4% at normal incidence.
+        reflectance: f32,
+    },
+    /// Emits `radiance` and reflects no light. Emissive surfaces only light other surfaces if
+    /// they are [emitters](Scene::emitters) too.
+    Emissive {
+        radiance: LinearRgb,
+    },
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Light transport algorithm used by the [`Renderer`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    /// Direct lighting with shadow rays plus perfect reflection and refraction, and one sampled
+    /// reflection for [`Material::Microfacet`]. Indirect light is approximated by
+    /// [`Light::Ambient`].
+    #[default]
+    Whitted,
+    /// Unbiased Monte Carlo path tracing with bounces importance sampled from the BRDF, next-event
+    /// estimation and Russian roulette. [`Light::Ambient`] is ignored, the camera background acts
+    /// as environment light instead.
+    PathTracing,
+}
+
+/// Rectangular region of the image, rendered as one unit of work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub min: UVec2,
+    pub size: UVec2,
+}
+
+/// Tiles of one render shared by multiple workers, see [`Renderer::render_queued_tiles`].
+#[derive(Debug, Default)]
+pub struct TileQueue {
+    next_tile: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl TileQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop handing out tiles. Workers return once their current tile is done.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Camera {
     pub translation: Vec3,
     pub direction: Dir3,
@@ -73,6 +269,14 @@ pub struct Renderer {
 
     shadow_bias: f32,
     max_recursion_depth: u32,
+    integrator: Integrator,
+
+    samples_per_pixel: u32,
+    jitter: bool,
+    seed: u64,
+
+    tile_size: u32,
+    threads: usize,
 }
 
 impl Renderer {
@@ -106,59 +310,224 @@ impl Renderer {
 
             shadow_bias: 0.001,
             max_recursion_depth: 10,
+            integrator: Integrator::Whitted,
+
+            samples_per_pixel: 1,
+            jitter: false,
+            seed: 0,
+
+            tile_size: 16,
+            threads: 0,
         }
     }
 
+    pub fn with_integrator(mut self, integrator: Integrator) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    /// Number of rays shot through each pixel. A single sample goes through the pixel center,
+    /// multiple samples are spread over the pixel with stratified jitter and averaged.
+    pub fn with_samples_per_pixel(mut self, samples_per_pixel: u32) -> Self {
+        self.samples_per_pixel = samples_per_pixel.max(1);
+        self
+    }
+
+    /// Jitter single-sample pixels as well instead of shooting through the pixel center. Useful
+    /// when accumulating multiple frames rendered with different seeds.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Seed for the sub-pixel jitter. The same seed always produces the same image.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Edge length of the square tiles the image is split into.
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size.max(1);
+        self
+    }
+
+    /// Number of worker threads, 0 uses all available cores.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn dimensions(&self) -> UVec2 {
+        self.dimensions
+    }
+
     pub fn render<S: Scene + Send + Sync>(&self, scene: &S) -> Vec<Color> {
         let mut pixels = vec![Color::BLACK; (self.dimensions.x * self.dimensions.y) as usize];
         self.render_into(scene, &mut pixels);
         pixels
     }
 
-    // TODO: Do in parallel on non-wasm targets
     pub fn render_into<S: Scene + Send + Sync>(&self, scene: &S, pixels: &mut [Color]) {
         assert!(pixels.len() == (self.dimensions.x * self.dimensions.y) as usize);
 
-        // for y in 0..self.dimensions.y {
-        //     let offset = (y * self.dimensions.x) as usize;
-        //     for x in 0..self.dimensions.x {
-        //         let pixel = self.render_pixel(scene, UVec2::new(x, y));
-        //         pixels[offset + x as usize] = pixel;
-        //     }
-        // }
-
-        let threads = thread::available_parallelism()
-            .map(|n| n.get())
-            .unwrap_or(1);
-        let chunk_size = pixels.len() / threads;
+        let pixels = Mutex::new(pixels);
+        self.render_tiles(scene, |tile, tile_pixels| {
+            let mut pixels = pixels.lock().unwrap();
+            for (y, row) in tile_pixels.chunks(tile.size.x as usize).enumerate() {
+                let offset = ((tile.min.y + y as u32) * self.dimensions.x + tile.min.x) as usize;
+                pixels[offset..offset + row.len()].copy_from_slice(row);
+            }
+        });
+    }
+
+    /// Render all tiles, calling `on_tile` with the pixels of each tile as soon as it is done.
+    /// Workers pull tiles from a shared queue, so expensive regions don't leave threads idle.
+    /// `on_tile` is called from the worker threads in no particular order.
+    ///
+    /// On wasm, threads are not available and all tiles are rendered on the calling thread.
+    pub fn render_tiles<S, F>(&self, scene: &S, on_tile: F)
+    where
+        S: Scene + Send + Sync,
+        F: Fn(Tile, &[Color]) + Sync,
+    {
+        let threads = match self.threads {
+            0 if cfg!(target_arch = "wasm32") => 1,
+            0 => thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            threads => threads,
+        };
+        let queue = TileQueue::new();
+        let worker = || self.render_queued_tiles(scene, &queue, &on_tile);
+
+        if threads <= 1 || cfg!(target_arch = "wasm32") {
+            worker();
+            return;
+        }
+
         thread::scope(|s| {
-            for (chunk_index, chunk) in pixels.chunks_mut(chunk_size).enumerate() {
-                s.spawn(move || {
-                    let offset = chunk_index * chunk_size;
-                    for (i, pixel) in chunk.iter_mut().enumerate() {
-                        let index = offset + i;
-                        let x = (index % (self.dimensions.x as usize)) as u32;
-                        let y = (index / (self.dimensions.x as usize)) as u32;
-                        *pixel = self.render_pixel(scene, UVec2::new(x, y));
-                    }
-                });
+            for _ in 0..threads.min(self.tile_count()) {
+                s.spawn(worker);
             }
         });
     }
 
+    /// Render tiles taken from `queue` until there are none left or the queue is cancelled,
+    /// calling `on_tile` with the pixels of each tile. Workers on other threads can share the
+    /// queue, like the ones of [`Renderer::render_tiles`].
+    pub fn render_queued_tiles<S: Scene>(
+        &self,
+        scene: &S,
+        queue: &TileQueue,
+        mut on_tile: impl FnMut(Tile, &[Color]),
+    ) {
+        let mut pixels = Vec::new();
+        while !queue.is_cancelled() {
+            let index = queue.next_tile.fetch_add(1, Ordering::Relaxed);
+            if index >= self.tile_count() {
+                break;
+            }
+            let tile = self.tile(index);
+            self.render_tile(scene, tile, &mut pixels);
+            on_tile(tile, &pixels);
+        }
+    }
+
+    /// Render the rows in `rows` into `pixels`, which holds exactly these rows. This allows
+    /// spreading a render over multiple calls, e.g. one batch of rows per frame on
+    /// single-threaded targets.
+    pub fn render_rows<S: Scene>(&self, scene: &S, rows: Range<u32>, pixels: &mut [Color]) {
+        assert!(rows.end <= self.dimensions.y);
+        assert!(pixels.len() == (rows.len() as u32 * self.dimensions.x) as usize);
+
+        for (pixel, index) in pixels.iter_mut().zip(0..) {
+            let x = index % self.dimensions.x;
+            let y = rows.start + index / self.dimensions.x;
+            *pixel = self.render_pixel(scene, UVec2::new(x, y));
+        }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        let tiles = self.tiles_per_axis();
+        (tiles.x * tiles.y) as usize
+    }
+
+    /// Tiles are numbered row by row, starting at the top left.
+    pub fn tile(&self, index: usize) -> Tile {
+        let tiles = self.tiles_per_axis();
+        let min = UVec2::new(index as u32 % tiles.x, index as u32 / tiles.x) * self.tile_size;
+        Tile {
+            min,
+            size: (min + self.tile_size).min(self.dimensions) - min,
+        }
+    }
+
+    /// Render a single tile into `pixels`, row by row.
+    pub fn render_tile<S: Scene>(&self, scene: &S, tile: Tile, pixels: &mut Vec<Color>) {
+        pixels.clear();
+        for y in tile.min.y..tile.min.y + tile.size.y {
+            for x in tile.min.x..tile.min.x + tile.size.x {
+                pixels.push(self.render_pixel(scene, UVec2::new(x, y)));
+            }
+        }
+    }
+
+    fn tiles_per_axis(&self) -> UVec2 {
+        (self.dimensions + self.tile_size - 1) / self.tile_size
+    }
+
     pub fn render_pixel<S: Scene>(&self, scene: &S, pixel: UVec2) -> Color {
+        let mut rng = Rng::new(self.seed, (pixel.y * self.dimensions.x + pixel.x) as u64);
+
+        if self.samples_per_pixel == 1 && !self.jitter {
+            return self
+                .render_sample(scene, pixel, Vec2::ZERO, &mut rng)
+                .into();
+        }
+
+        // Stratify the largest square number of samples, jitter the rest freely
+        let strata = (self.samples_per_pixel as f32).sqrt() as u32;
+        let mut result = LinearRgb::BLACK;
+        for i in 0..self.samples_per_pixel {
+            let jitter = Vec2::new(rng.next_f32(), rng.next_f32());
+            let offset = if i < strata * strata {
+                (UVec2::new(i % strata, i / strata).as_vec2() + jitter) / strata as f32
+            } else {
+                jitter
+            };
+            result += self.render_sample(scene, pixel, offset - 0.5, &mut rng);
+        }
+
+        (result / self.samples_per_pixel as f32).into()
+    }
+
+    fn render_sample<S: Scene>(
+        &self,
+        scene: &S,
+        pixel: UVec2,
+        offset: Vec2,
+        rng: &mut Rng,
+    ) -> LinearRgb {
         let pixel = self.top_left_pixel
-            + (pixel.x as f32) * self.pixel_delta_u
-            + (pixel.y as f32) * self.pixel_delta_v;
+            + (pixel.x as f32 + offset.x) * self.pixel_delta_u
+            + (pixel.y as f32 + offset.y) * self.pixel_delta_v;
         let ray = Ray3d {
             origin: self.camera.translation,
             direction: Dir3::new(pixel - self.camera.translation).unwrap(),
         };
 
-        self.cast_ray(scene, ray, 0).into()
+        match self.integrator {
+            Integrator::Whitted => self.cast_ray(scene, ray, 0, rng),
+            Integrator::PathTracing => self.trace_path(scene, ray, rng),
+        }
     }
 
-    fn cast_ray<S: Scene>(&self, scene: &S, ray: Ray3d, depth: u32) -> LinearRgb {
+    fn cast_ray<S: Scene>(&self, scene: &S, ray: Ray3d, depth: u32, rng: &mut Rng) -> LinearRgb {
         if depth >= self.max_recursion_depth {
             return self.camera.background;
         }
@@ -168,18 +537,29 @@ impl Renderer {
         };
 
         match surface.material {
-            Material::Diffuse { albedo } => {
-                self.shade_diffuse(scene, albedo, surface.position, surface.normal)
-            }
+            Material::Diffuse { albedo } => self.shade_diffuse(
+                scene,
+                &Brdf::Lambert { albedo },
+                surface.position,
+                surface.normal,
+                rng,
+            ),
             Material::Reflective {
                 albedo,
                 reflectivity,
             } => {
-                let this = self.shade_diffuse(scene, albedo, surface.position, surface.normal);
+                let this = self.shade_diffuse(
+                    scene,
+                    &Brdf::Lambert { albedo },
+                    surface.position,
+                    surface.normal,
+                    rng,
+                );
                 let reflected = self.cast_ray(
                     scene,
                     self.reflect_ray(ray.direction, surface.position, surface.normal),
                     depth + 1,
+                    rng,
                 );
                 LinearRgb::mix(&this, &reflected, reflectivity)
             }
@@ -199,6 +579,7 @@ impl Renderer {
                             index,
                         ),
                         depth + 1,
+                        rng,
                     )
                 } else {
                     LinearRgb::BLACK
@@ -207,65 +588,369 @@ impl Renderer {
                     scene,
                     self.reflect_ray(ray.direction, surface.position, surface.normal),
                     depth + 1,
+                    rng,
                 );
 
                 LinearRgb::mix(&(albedo * refracted * transparency), &reflected, kr)
             }
+            Material::Microfacet {
+                base_color,
+                roughness,
+                metallic,
+                reflectance,
+            } => {
+                let brdf =
+                    Brdf::microfacet(base_color, roughness, metallic, reflectance, -ray.direction);
+                let this = self.shade_diffuse(scene, &brdf, surface.position, surface.normal, rng);
+                // Faint reflections, like those of rough dielectrics, are only traced sometimes
+                let reflected = match brdf.sample_specular(surface.normal, rng) {
+                    Some((direction, weight)) => {
+                        let probability = weight.max_element().min(1.0);
+                        match rng.next_f32() < probability {
+                            true => {
+                                let ray = Ray3d {
+                                    origin: surface.position
+                                        + self.shadow_bias * (*surface.normal + *direction),
+                                    direction,
+                                };
+                                weight / probability * self.cast_ray(scene, ray, depth + 1, rng)
+                            }
+                            false => LinearRgb::BLACK,
+                        }
+                    }
+                    None => LinearRgb::BLACK,
+                };
+
+                this + reflected
+            }
+            Material::Emissive { radiance } => radiance,
         }
     }
 
     fn shade_diffuse<S: Scene>(
         &self,
         scene: &S,
-        albedo: LinearRgb,
+        brdf: &Brdf,
         surface_position: Vec3,
         surface_normal: Dir3,
+        rng: &mut Rng,
     ) -> LinearRgb {
         let mut result = LinearRgb::BLACK;
 
         for light in scene.lights() {
-            match *light {
-                Light::Ambient { color, intensity } => {
-                    result += albedo * color * intensity;
-                }
-                Light::Directional {
-                    direction,
-                    color,
-                    intensity,
-                } => {
-                    let dir_to_light = -direction;
+            result += self.shade_light(scene, light, brdf, surface_position, surface_normal, rng);
+        }
+
+        result + self.shade_emitter(scene, brdf, surface_position, surface_normal, rng)
+    }
+
+    /// Direct light from one emitter picked by power, divided by the chance of picking it.
+    /// Specular reflections of emitters are left to the rays hitting them.
+    fn shade_emitter<S: Scene>(
+        &self,
+        scene: &S,
+        brdf: &Brdf,
+        surface_position: Vec3,
+        surface_normal: Dir3,
+        rng: &mut Rng,
+    ) -> LinearRgb {
+        let Some((light, probability)) = scene.emitters().pick(rng) else {
+            return LinearRgb::BLACK;
+        };
+        let reflected = self.shade_light(
+            scene,
+            light,
+            &brdf.diffuse(),
+            surface_position,
+            surface_normal,
+            rng,
+        );
+        reflected / probability
+    }
+
+    fn shade_light<S: Scene>(
+        &self,
+        scene: &S,
+        light: &Light,
+        brdf: &Brdf,
+        surface_position: Vec3,
+        surface_normal: Dir3,
+        rng: &mut Rng,
+    ) -> LinearRgb {
+        match *light {
+            Light::Ambient { color, intensity } => brdf.diffuse_albedo() * color * intensity,
+            Light::Directional {
+                direction,
+                angular_diameter,
+                color,
+                intensity,
+                shadow_samples,
+            } => {
+                let cos_max_angle = (angular_diameter / 2.0).cos();
+                let count = shadow_sample_count(angular_diameter > 0.0, shadow_samples);
+                let reflected = average_samples(count, rng, |rng| {
+                    let dir_to_light = cone_direction(-direction, cos_max_angle, rng);
                     let shadow_ray =
                         self.shadow_ray(surface_position, surface_normal, dir_to_light);
-                    let light_intensity = match scene.cast_ray(shadow_ray, f32::INFINITY) {
-                        Some(_) => continue,
-                        None => intensity,
+                    match scene.cast_ray(shadow_ray, f32::INFINITY) {
+                        Some(_) => LinearRgb::BLACK,
+                        None => {
+                            brdf.evaluate(surface_normal, dir_to_light)
+                                * (surface_normal.dot(*dir_to_light).max(0.0) * intensity)
+                        }
+                    }
+                });
+
+                color * reflected
+            }
+            Light::Point {
+                position,
+                radius,
+                color,
+                intensity,
+                shadow_samples,
+            } => {
+                let count = shadow_sample_count(radius > 0.0, shadow_samples);
+                let reflected = average_samples(count, rng, |rng| {
+                    // The sphere is approximated by its disk facing the surface
+                    let point = match Dir3::new(surface_position - position) {
+                        Ok(normal) => disk_point(position, normal, radius, rng),
+                        Err(_) => position,
                     };
-                    let light_power = surface_normal.dot(*dir_to_light).max(0.0) * light_intensity;
+                    let Ok(dir_to_light) = Dir3::new(point - surface_position) else {
+                        return LinearRgb::BLACK;
+                    };
+                    let shadow_ray =
+                        self.shadow_ray(surface_position, surface_normal, dir_to_light);
+                    let distance_squared = Vec3::distance_squared(point, surface_position);
+                    match scene.cast_ray(shadow_ray, distance_squared.sqrt()) {
+                        Some(_) => LinearRgb::BLACK,
+                        None => {
+                            brdf.evaluate(surface_normal, dir_to_light)
+                                * (surface_normal.dot(*dir_to_light).max(0.0) * intensity
+                                    / (4.0 * PI * distance_squared))
+                        }
+                    }
+                });
 
-                    result += albedo * color * light_power / PI;
+                color * reflected
+            }
+            Light::Rect {
+                corner,
+                u,
+                v,
+                color,
+                intensity,
+                shadow_samples,
+            } => {
+                let reflected = average_samples(shadow_samples.max(1), rng, |rng| {
+                    self.area_light_sample(
+                        scene,
+                        &[(corner, u, v)],
+                        brdf,
+                        surface_position,
+                        surface_normal,
+                        rng,
+                    )
+                });
+                color * intensity * reflected
+            }
+            Light::Box {
+                min,
+                max,
+                color,
+                intensity,
+                shadow_samples,
+            } => {
+                let reflected = average_samples(shadow_samples.max(1), rng, |rng| {
+                    self.area_light_sample(
+                        scene,
+                        &box_faces(min, max),
+                        brdf,
+                        surface_position,
+                        surface_normal,
+                        rng,
+                    )
+                });
+                color * intensity * reflected
+            }
+        }
+    }
+
+    /// Radiance reflected by the surface from parallelograms emitting a radiance of 1, each given
+    /// as corner and edges `u` and `v` and emitting to the side of `u × v`, estimated with a single
+    /// shadow ray. Up to 6 faces are supported. The shadow ray picks a face by its solid angle and
+    /// a point on it uniformly.
+    fn area_light_sample<S: Scene>(
+        &self,
+        scene: &S,
+        faces: &[(Vec3, Vec3, Vec3)],
+        brdf: &Brdf,
+        surface_position: Vec3,
+        surface_normal: Dir3,
+        rng: &mut Rng,
+    ) -> LinearRgb {
+        // Approximate solid angle of the faces, zero for faces turned away from the surface
+        let mut weights = [0.0; 6];
+        for (weight, &(corner, u, v)) in weights.iter_mut().zip(faces) {
+            let to_center = corner + (u + v) / 2.0 - surface_position;
+            let cos_light = -u.cross(v).dot(to_center) / to_center.length();
+            *weight = cos_light.max(0.0) / to_center.length_squared();
+        }
+        let total_weight = weights.iter().sum::<f32>();
+        if total_weight <= 0.0 {
+            return LinearRgb::BLACK;
+        }
+
+        // Pick a face
+        let mut target = rng.next_f32() * total_weight;
+        let index = weights[..faces.len()]
+            .iter()
+            .position(|&weight| {
+                target -= weight;
+                target < 0.0
+            })
+            .unwrap_or_else(|| weights.iter().rposition(|&weight| weight > 0.0).unwrap());
+        let (corner, u, v) = faces[index];
+
+        // Pick a point on it
+        let point = corner + rng.next_f32() * u + rng.next_f32() * v;
+        let Ok(dir_to_light) = Dir3::new(point - surface_position) else {
+            return LinearRgb::BLACK;
+        };
+        let normal = u.cross(v);
+        let area = normal.length();
+        let cos_surface = surface_normal.dot(*dir_to_light);
+        let cos_light = -normal.dot(*dir_to_light) / area;
+        if cos_surface <= 0.0 || cos_light <= 0.0 {
+            return LinearRgb::BLACK;
+        }
+
+        // Stop the shadow ray just before the light, whose surface may be part of the scene
+        let shadow_ray = self.shadow_ray(surface_position, surface_normal, dir_to_light);
+        let distance = shadow_ray.origin.distance(point) - self.shadow_bias;
+        if scene.cast_ray(shadow_ray, distance).is_some() {
+            return LinearRgb::BLACK;
+        }
+
+        // Area sampling pdf converted to solid angle, divided by the chance of the face
+        let distance_squared = surface_position.distance_squared(point);
+        let probability = weights[index] / total_weight;
+        brdf.evaluate(surface_normal, dir_to_light)
+            * (cos_surface * cos_light * area / distance_squared / probability)
+    }
+
+    fn trace_path<S: Scene>(&self, scene: &S, mut ray: Ray3d, rng: &mut Rng) -> LinearRgb {
+        let mut radiance = LinearRgb::BLACK;
+        let mut throughput = LinearRgb::WHITE;
+        // Camera rays and specular bounces don't sample emitters directly
+        let mut is_specular = true;
+
+        for depth in 0..self.max_recursion_depth {
+            let Some(surface) = scene.cast_ray(ray, f32::INFINITY) else {
+                radiance += throughput * self.camera.background;
+                break;
+            };
+
+            // Pick one of the lobes of the material stochastically
+            let brdf = match surface.material {
+                Material::Emissive { radiance: emitted } => {
+                    // Emitters hit after diffuse bounces were already sampled directly
+                    if is_specular {
+                        radiance += throughput * emitted;
+                    }
+                    break;
                 }
-                Light::Point {
-                    position,
-                    color,
-                    intensity,
+                Material::Diffuse { albedo } => Some(Brdf::Lambert { albedo }),
+                Material::Reflective {
+                    albedo,
+                    reflectivity,
                 } => {
-                    let dir_to_light = Dir3::new(position - surface_position).unwrap();
-                    let shadow_ray =
-                        self.shadow_ray(surface_position, surface_normal, dir_to_light);
-                    let distance_squared = Vec3::distance_squared(position, surface_position);
-                    let light_intensity = match scene.cast_ray(shadow_ray, distance_squared.sqrt())
-                    {
-                        Some(_) => continue,
-                        None => intensity / (4.0 * PI * distance_squared),
-                    };
-                    let light_power = surface_normal.dot(*dir_to_light).max(0.0) * light_intensity;
+                    if rng.next_f32() < reflectivity {
+                        ray = self.reflect_ray(ray.direction, surface.position, surface.normal);
+                        None
+                    } else {
+                        Some(Brdf::Lambert { albedo })
+                    }
+                }
+                Material::Refractive {
+                    albedo,
+                    index,
+                    transparency,
+                } => {
+                    let kr = fresnel(ray.direction, surface.normal, index);
+                    if rng.next_f32() < kr {
+                        ray = self.reflect_ray(ray.direction, surface.position, surface.normal);
+                    } else {
+                        ray = self.transmission_ray(
+                            ray.direction,
+                            surface.position,
+                            surface.normal,
+                            index,
+                        );
+                        throughput = throughput * albedo * transparency;
+                    }
+                    None
+                }
+                Material::Microfacet {
+                    base_color,
+                    roughness,
+                    metallic,
+                    reflectance,
+                } => Some(Brdf::microfacet(
+                    base_color,
+                    roughness,
+                    metallic,
+                    reflectance,
+                    -ray.direction,
+                )),
+            };
+
+            is_specular = brdf.is_none();
+
+            if let Some(brdf) = brdf {
+                // Next-event estimation: direct light is sampled explicitly, so lights are never
+                // hit by bounce rays
+                for light in scene.lights() {
+                    if !matches!(light, Light::Ambient { .. }) {
+                        radiance += throughput
+                            * self.shade_light(
+                                scene,
+                                light,
+                                &brdf,
+                                surface.position,
+                                surface.normal,
+                                rng,
+                            );
+                    }
+                }
+                radiance += throughput
+                    * self.shade_emitter(scene, &brdf, surface.position, surface.normal, rng);
+
+                // Bounce in a direction sampled from the BRDF
+                let Some(sample) = brdf.sample(surface.normal, rng) else {
+                    break;
+                };
+                ray = Ray3d {
+                    origin: surface.position
+                        + self.shadow_bias * (*surface.normal + *sample.direction),
+                    direction: sample.direction,
+                };
+                throughput = throughput * sample.weight;
+                is_specular = sample.is_specular;
+            }
 
-                    result += albedo * color * light_power / PI;
+            // Russian roulette
+            if depth >= 3 {
+                let survival = throughput.max_element().min(0.95);
+                if rng.next_f32() >= survival {
+                    break;
                 }
+                throughput = throughput / survival;
             }
         }
 
-        result
+        radiance
     }
 
     fn shadow_ray(
@@ -314,6 +999,62 @@ impl Renderer {
     }
 }
 
+/// Sides of the box from `min` to `max` as corner and edges, with `u × v` pointing outwards.
+fn box_faces(min: Vec3, max: Vec3) -> [(Vec3, Vec3, Vec3); 6] {
+    let size = max - min;
+    let (x, y, z) = (size.x * Vec3::X, size.y * Vec3::Y, size.z * Vec3::Z);
+    [
+        (min, z, y),
+        (min + x, y, z),
+        (min, x, z),
+        (min + y, z, x),
+        (min, y, x),
+        (min + z, x, y),
+    ]
+}
+
+/// Direction uniformly distributed over the cone around `axis` whose angle has the cosine
+/// `cos_max_angle`.
+fn cone_direction(axis: Dir3, cos_max_angle: f32, rng: &mut Rng) -> Dir3 {
+    let (tangent, bitangent) = axis.any_orthonormal_pair();
+    let phi = 2.0 * PI * rng.next_f32();
+    let cos_theta = 1.0 - rng.next_f32() * (1.0 - cos_max_angle);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    Dir3::new(
+        sin_theta * phi.cos() * tangent + sin_theta * phi.sin() * bitangent + cos_theta * *axis,
+    )
+    .unwrap_or(axis)
+}
+
+/// Number of shadow rays for a light, a single one for lights without a size.
+fn shadow_sample_count(has_size: bool, shadow_samples: u32) -> u32 {
+    match has_size {
+        true => shadow_samples.max(1),
+        false => 1,
+    }
+}
+
+/// Average of `count` calls of `sample`.
+fn average_samples(
+    count: u32,
+    rng: &mut Rng,
+    mut sample: impl FnMut(&mut Rng) -> LinearRgb,
+) -> LinearRgb {
+    let mut sum = LinearRgb::BLACK;
+    for _ in 0..count {
+        sum += sample(rng);
+    }
+    sum / count as f32
+}
+
+/// Point uniformly distributed over the disk of `radius` around `center`, facing `normal`.
+fn disk_point(center: Vec3, normal: Dir3, radius: f32, rng: &mut Rng) -> Vec3 {
+    let (tangent, bitangent) = normal.any_orthonormal_pair();
+    let phi = 2.0 * PI * rng.next_f32();
+    let r = radius * rng.next_f32().sqrt();
+    center + r * phi.cos() * tangent + r * phi.sin() * bitangent
+}
+
 fn fresnel(direction: Dir3, normal: Dir3, index: f32) -> f32 {
     let dir_dot_n = direction.dot(*normal);
     let mut eta_i = 1.0;
@@ -334,3 +1075,138 @@ fn fresnel(direction: Dir3, normal: Dir3, index: f32) -> f32 {
         (r_s * r_s + r_p * r_p) / 2.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A glossy sphere on a floor at y = 0, lit by a point light with a radius.
+    struct TestScene {
+        lights: Vec<Light>,
+    }
+
+    impl TestScene {
+        const SPHERE_CENTER: Vec3 = Vec3::new(0.0, 0.5, 0.0);
+        const SPHERE_RADIUS: f32 = 0.5;
+
+        fn new() -> Self {
+            Self {
+                lights: vec![
+                    Light::Point {
+                        position: Vec3::new(1.0, 3.0, 1.0),
+                        radius: 0.3,
+                        color: LinearRgb::WHITE,
+                        intensity: 20.0,
+                        shadow_samples: 2,
+                    },
+                    Light::Ambient {
+                        color: LinearRgb::WHITE,
+                        intensity: 0.1,
+                    },
+                ],
+            }
+        }
+    }
+
+    impl Scene for TestScene {
+        fn lights(&self) -> &[Light] {
+            &self.lights
+        }
+
+        fn cast_ray(&self, ray: Ray3d, max_distance: f32) -> Option<RayHit> {
+            let mut hit = None;
+
+            let distance = -ray.origin.y / ray.direction.y;
+            if distance > 0.0 && distance < max_distance {
+                hit = Some(RayHit {
+                    material: Material::Diffuse {
+                        albedo: LinearRgb::new(0.5, 0.5, 0.5),
+                    },
+                    position: ray.get_point(distance),
+                    normal: Dir3::Y,
+                    distance,
+                });
+            }
+
+            let offset = ray.origin - Self::SPHERE_CENTER;
+            let b = offset.dot(*ray.direction);
+            let discriminant = b * b - offset.length_squared() + Self::SPHERE_RADIUS.powi(2);
+            let distance = -b - discriminant.sqrt();
+            if discriminant >= 0.0
+                && distance > 0.0
+                && distance < hit.map_or(max_distance, |hit| hit.distance)
+            {
+                let position = ray.get_point(distance);
+                hit = Some(RayHit {
+                    material: Material::Microfacet {
+                        base_color: LinearRgb::new(0.8, 0.2, 0.1),
+                        roughness: 0.4,
+                        metallic: 0.0,
+                        reflectance: 0.5,
+                    },
+                    position,
+                    normal: Dir3::new(position - Self::SPHERE_CENTER).unwrap(),
+                    distance,
+                });
+            }
+
+            hit
+        }
+    }
+
+    fn renderer(integrator: Integrator, seed: u64) -> Renderer {
+        let camera = Camera {
+            translation: Vec3::new(0.0, 2.0, 4.0),
+            direction: Dir3::new(Vec3::new(0.0, -0.4, -1.0)).unwrap(),
+            up: Dir3::Y,
+            fov: 1.0,
+            background: LinearRgb::new(0.5, 0.5, 1.0),
+        };
+        Renderer::init(camera, UVec2::new(24, 16))
+            .with_integrator(integrator)
+            .with_samples_per_pixel(2)
+            .with_jitter(true)
+            .with_seed(seed)
+            .with_tile_size(8)
+            .with_threads(3)
+    }
+
+    #[test]
+    fn emitters_are_picked_by_power() {
+        let emitter = |min: Vec3, intensity| Light::Box {
+            min,
+            max: min + 1.0,
+            color: LinearRgb::WHITE,
+            intensity,
+            shadow_samples: 1,
+        };
+        let emitters = Emitters::new(vec![
+            emitter(Vec3::ZERO, 1.0),
+            emitter(Vec3::X, 0.0),
+            emitter(Vec3::Y, 3.0),
+        ]);
+
+        let mut rng = Rng::new(0, 0);
+        let mut picks = [0; 3];
+        for _ in 0..10000 {
+            let (light, probability) = emitters.pick(&mut rng).unwrap();
+            let index = emitters.lights().iter().position(|l| l == light).unwrap();
+            assert!((probability - [0.25, 0.0, 0.75][index]).abs() < 1e-6);
+            picks[index] += 1;
+        }
+        assert_eq!(picks[1], 0);
+        assert!((picks[2] as f32 / 10000.0 - 0.75).abs() < 0.02);
+
+        assert!(Emitters::default().pick(&mut rng).is_none());
+    }
+
+    #[test]
+    fn renders_depend_only_on_the_seed() {
+        let scene = TestScene::new();
+        for integrator in [Integrator::Whitted, Integrator::PathTracing] {
+            let image = renderer(integrator, 1).render(&scene);
+            assert_eq!(renderer(integrator, 1).render(&scene), image);
+            assert_ne!(renderer(integrator, 2).render(&scene), image);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Headless renderer: ray traces a blox scene with lux and writes the result to an image file,
+//! without opening a window or initializing the GPU.
+
+use bevy::prelude::*;
+use blox::{BlockRegistry, BlockRegistryError, BlockTextures, BloxScene, LuxScene, RenderedFrame};
+use std::{path::PathBuf, process::ExitCode, str::FromStr, sync::Arc};
+
+const USAGE: &str = "\
+Usage: blox-render [options]
+
+Options:
+  --scene <path>        Scene saved by the game, .ron, .vox, .schem or .litematic file
+                        (default: built-in scene)
+  --output <path>       Output image, .png, .exr or .hdr (default: render.png)
+  --assets <dir>        Assets folder with the block registry (default: assets)
+  --width <pixels>      Image width (default: 1280)
+  --height <pixels>     Image height (default: 720)
+  --eye <x,y,z>         Camera position (default: diagonally above the target)
+  --target <x,y,z>      Point the camera looks at (default: center of the scene ground)
+  --fov <degrees>       Vertical field of view (default: 45)
+  --samples <n>         Samples per pixel (default: 16)
+  --shadow-samples <n>  Shadow rays per light and shaded point, for soft shadows (default: 4)
+  --seed <n>            Seed for the sampling (default: 0)
+  --path-tracing        Use the path tracing integrator
+  --help                Print this help";
+
+#[derive(Debug)]
+struct Args {
+    scene: Option<PathBuf>,
+    output: PathBuf,
+    assets: PathBuf,
+    dimensions: UVec2,
+    eye: Option<Vec3>,
+    target: Option<Vec3>,
+    fov: f32,
+    samples: u32,
+    shadow_samples: u32,
+    seed: u64,
+    integrator: lux::Integrator,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut parsed = Self {
+            scene: None,
+            output: PathBuf::from("render.png"),
+            assets: PathBuf::from("assets"),
+            dimensions: UVec2::new(1280, 720),
+            eye: None,
+            target: None,
+            fov: 45.0,
+            samples: 16,
+            shadow_samples: 4,
+            seed: 0,
+            integrator: lux::Integrator::Whitted,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--scene" => parsed.scene = Some(PathBuf::from(value()?)),
+                "--output" => parsed.output = PathBuf::from(value()?),
+                "--assets" => parsed.assets = PathBuf::from(value()?),
+                "--width" => parsed.dimensions.x = parse_number(&arg, &value()?)?,
+                "--height" => parsed.dimensions.y = parse_number(&arg, &value()?)?,
+                "--eye" => parsed.eye = Some(parse_vec3(&arg, &value()?)?),
+                "--target" => parsed.target = Some(parse_vec3(&arg, &value()?)?),
+                "--fov" => parsed.fov = parse_number(&arg, &value()?)?,
+                "--samples" => parsed.samples = parse_number(&arg, &value()?)?,
+                "--shadow-samples" => parsed.shadow_samples = parse_number(&arg, &value()?)?,
+                "--seed" => parsed.seed = parse_number(&arg, &value()?)?,
+                "--path-tracing" => parsed.integrator = lux::Integrator::PathTracing,
+                "--help" => return Ok(None),
+                _ => return Err(format!("unknown argument {arg}")),
+            }
+        }
+
+        if parsed.dimensions.cmpeq(UVec2::ZERO).any() {
+            return Err("width and height must be positive".to_string());
+        }
+
+        Ok(Some(parsed))
+    }
+}
+
+fn parse_number<T: FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {arg}: {value}"))
+}
+
+fn parse_vec3(arg: &str, value: &str) -> Result<Vec3, String> {
+    let components = value
+        .split(',')
+        .map(|component| parse_number(arg, component.trim()))
+        .collect::<Result<Vec<f32>, _>>()?;
+    match components[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("invalid value for {arg}: {value}, expected x,y,z")),
+    }
+}
+
+/// Load the block registry from the assets folder.
+fn load_registry(assets: &std::path::Path) -> Result<Arc<BlockRegistry>, String> {
+    let path = assets.join("blocks.registry.ron");
+    let registry = std::fs::read_to_string(&path)
+        .map_err(BlockRegistryError::from)
+        .and_then(|text| BlockRegistry::from_ron(&text))
+        .map_err(|err| format!("failed to load {}: {err}", path.display()))?;
+    Ok(Arc::new(registry))
+}
+
+/// Load the textures of the blocks of `registry` from the assets folder.
+fn load_textures(
+    assets: &std::path::Path,
+    registry: Arc<BlockRegistry>,
+) -> Result<BlockTextures, String> {
+    let images = registry
+        .texture_paths()
+        .iter()
+        .map(|path| {
+            let path = assets.join(path);
+            image::open(&path)
+                .map(|image| image.to_rgba8())
+                .map_err(|err| format!("failed to load {}: {err}", path.display()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(BlockTextures::from_rgba8(
+        registry,
+        images.iter().map(|image| {
+            (
+                UVec2::new(image.width(), image.height()),
+                image.as_raw().as_slice(),
+            )
+        }),
+    ))
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let registry = load_registry(&args.assets)?;
+    let scene = match &args.scene {
+        Some(path) => BloxScene::load(path, &registry)
+            .map_err(|err| format!("failed to load {}: {err}", path.display()))?,
+        None => blox::default_scene(),
+    };
+
+    // Same view as the game camera
+    let (min, max) = scene.bounds().unwrap_or_default();
+    let center = (min + max).as_vec3() / 2.0;
+    let target = args
+        .target
+        .unwrap_or(Vec3::new(center.x, min.y as f32, center.z));
+    let eye = args.eye.unwrap_or(target + Vec3::new(19.6, 16.0, 19.6));
+
+    // Same lights as the game screen
+    let lights = vec![
+        blox::directional_light(
+            Dir3::new(Vec3::new(1.0, -0.5, -1.0)).unwrap(),
+            args.shadow_samples,
+        ),
+        blox::point_light(
+            Vec3::new(11.5, 5.5, 7.5),
+            blox::POINT_LIGHT_RADIUS,
+            args.shadow_samples,
+        ),
+        blox::ambient_light(),
+    ];
+    let textures = load_textures(&args.assets, registry)?;
+    let scene = LuxScene::new(lights, scene, textures, args.shadow_samples);
+
+    let direction = Dir3::new(target - eye)
+        .map_err(|_| "eye and target must be different points".to_string())?;
+    let renderer = lux::Renderer::init(
+        lux::Camera {
+            translation: eye,
+            direction,
+            up: Dir3::Y,
+            fov: args.fov.to_radians(),
+            background: blox::SKY_COLOR.into(),
+        },
+        args.dimensions,
+    )
+    .with_integrator(args.integrator)
+    .with_samples_per_pixel(args.samples)
+    .with_seed(args.seed);
+
+    let start = std::time::Instant::now();
+    let pixels = renderer.render(&scene);
+    println!("Rendered in {:?}", start.elapsed());
+
+    let frame = RenderedFrame {
+        dimensions: args.dimensions,
+        pixels: pixels.into_iter().map(lux::LinearRgb::from).collect(),
+    };
+    blox::save_frame(&frame, &args.output)
+        .map_err(|err| format!("failed to save {}: {err}", args.output.display()))?;
+    println!("Saved to {}", args.output.display());
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{AppState, screens::ScreenSetup, util::exp_lerp};
+use crate::{
+    AppState, key_bindings::KeyBindings, screens::ScreenSetup, util::exp_lerp, world::BloxWorld,
+};
 use bevy::{
     input::mouse::{MouseMotion, MouseScrollUnit},
     prelude::*,
@@ -19,7 +21,13 @@ pub fn plugin(app: &mut App) {
     // Update
     app.add_systems(
         Update,
-        (drag, update).chain().run_if(in_state(AppState::Game)),
+        (
+            frame_world.run_if(resource_added::<BloxWorld>),
+            drag,
+            update,
+        )
+            .chain()
+            .run_if(in_state(AppState::Game)),
     );
 }
 
@@ -50,13 +58,23 @@ struct Orbit {
 
 impl Orbit {
     const DEFAULT: Self = Self {
-        target: Transform::from_xyz(7.5, 0.0, 7.5),
+        target: Transform::IDENTITY,
         distance: 32.0,
         yaw: PI / 4.0,
         pitch: PI / 6.0,
     };
 }
 
+/// Orbit around the center of the ground under a newly created world.
+fn frame_world(world: Res<BloxWorld>, mut camera_controller: Single<&mut CameraController>) {
+    let Some((min, max)) = world.bounds() else {
+        return;
+    };
+    let center = (min + max).as_vec3() / 2.0;
+    camera_controller.orbit.target.translation = Vec3::new(center.x, min.y as f32, center.z);
+    camera_controller.prev_look = None;
+}
+
 fn drag(
     keyboard: Res<ButtonInput<KeyCode>>,
     mut camera_controller: Single<&mut CameraController>,
@@ -147,7 +165,13 @@ fn setup(mut commands: Commands, camera_controller: Single<Entity, With<CameraCo
         },
         Children::spawn(SpawnObserver::new(
             |trigger: Trigger<Pointer<Scroll>>,
-             mut camera_controller: Single<&mut CameraController>| {
+             mut camera_controller: Single<&mut CameraController>,
+             keyboard_input: Res<ButtonInput<KeyCode>>,
+             key_bindings: Res<KeyBindings>| {
+                // Selects a hotbar slot instead
+                if key_bindings.hotbar_scroll.pressed(&keyboard_input) {
+                    return;
+                }
                 let scroll = match trigger.unit {
                     MouseScrollUnit::Line => trigger.y / 5.0,
                     MouseScrollUnit::Pixel => trigger.y / 125.0 / 5.0,
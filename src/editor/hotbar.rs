@@ -0,0 +1,174 @@
+use super::BlockEditor;
+use crate::{
+    AppState,
+    key_bindings::KeyBindings,
+    screens::ScreenSetup,
+    world::{Block, BlockRegistry, BlockRegistryChanged, CurrentBlockRegistry},
+};
+use bevy::{input::mouse::AccumulatedMouseScroll, prelude::*};
+use bevy_spawn_observer::SpawnObserver;
+
+const SLOT_SIZE: f32 = 48.0;
+const SLOT_BORDER: f32 = 3.0;
+
+pub fn plugin(app: &mut App) {
+    // Setup
+    app.add_systems(OnEnter(AppState::Game), setup.after(ScreenSetup));
+
+    // Update
+    app.add_systems(
+        Update,
+        (
+            rebuild_slots.run_if(on_event::<BlockRegistryChanged>),
+            (select_with_keys, select_with_scroll),
+            highlight_selected
+                .run_if(resource_changed::<BlockEditor>.or(on_event::<BlockRegistryChanged>)),
+        )
+            .chain()
+            .run_if(in_state(AppState::Game)),
+    );
+}
+
+/// Blocks in slot order, every block of the registry that can be placed.
+fn slot_blocks(registry: &BlockRegistry) -> Vec<Block> {
+    registry.blocks().map(|(block, _)| block).collect()
+}
+
+/// Parent of the [`HotbarSlot`]s.
+#[derive(Component)]
+struct HotbarSlots;
+
+#[derive(Component)]
+struct HotbarSlot(Block);
+
+fn setup(mut commands: Commands, registry: Res<CurrentBlockRegistry>) {
+    commands
+        .spawn((
+            Name::new("Hotbar"),
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(8.0),
+                width: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            // Above the camera controller, which doesn't get the pointer events over the slots
+            GlobalZIndex(20),
+            Pickable::IGNORE,
+            StateScoped(AppState::Game),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    Node {
+                        padding: UiRect::all(Val::Px(4.0)),
+                        column_gap: Val::Px(4.0),
+                        ..default()
+                    },
+                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+                    HotbarSlots,
+                ))
+                .with_children(|parent| spawn_slots(parent, &registry));
+        });
+}
+
+/// A slot for every block of `registry`, showing its side texture like the block seen from the
+/// front.
+fn spawn_slots(parent: &mut ChildSpawnerCommands, registry: &BlockRegistry) {
+    for (block, definition) in registry.blocks() {
+        let image = &registry.images()[definition.texture_layer(IVec3::Z)];
+        parent.spawn((
+            HotbarSlot(block),
+            Node {
+                width: Val::Px(SLOT_SIZE),
+                height: Val::Px(SLOT_SIZE),
+                border: UiRect::all(Val::Px(SLOT_BORDER)),
+                ..default()
+            },
+            BorderColor(Color::NONE),
+            Children::spawn((
+                Spawn((
+                    ImageNode::new(image.clone()),
+                    Node {
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        ..default()
+                    },
+                    Pickable::IGNORE,
+                )),
+                SpawnObserver::new(
+                    move |_: Trigger<Pointer<Click>>, mut editor: ResMut<BlockEditor>| {
+                        editor.selected = block;
+                    },
+                ),
+            )),
+        ));
+    }
+}
+
+/// Replace the slots with those of the changed block registry.
+fn rebuild_slots(
+    mut commands: Commands,
+    hotbar: Single<Entity, With<HotbarSlots>>,
+    slots: Query<Entity, With<HotbarSlot>>,
+    registry: Res<CurrentBlockRegistry>,
+) {
+    for slot in &slots {
+        commands.entity(slot).despawn();
+    }
+    commands
+        .entity(*hotbar)
+        .with_children(|parent| spawn_slots(parent, &registry));
+}
+
+fn select_with_keys(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut editor: ResMut<BlockEditor>,
+    registry: Res<CurrentBlockRegistry>,
+) {
+    for (binding, block) in key_bindings.hotbar_slots.iter().zip(slot_blocks(&registry)) {
+        if binding.just_pressed(&keyboard_input) {
+            editor.selected = block;
+        }
+    }
+}
+
+/// Scrolling down anywhere with [`KeyBindings::hotbar_scroll`] held selects the next slot,
+/// wrapping around at the ends.
+fn select_with_scroll(
+    scroll: Res<AccumulatedMouseScroll>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut editor: ResMut<BlockEditor>,
+    registry: Res<CurrentBlockRegistry>,
+) {
+    // Some platforms scroll horizontally while shift is held
+    let delta = scroll.delta.y + scroll.delta.x;
+    if delta == 0.0 || !key_bindings.hotbar_scroll.pressed(&keyboard_input) {
+        return;
+    }
+
+    let blocks = slot_blocks(&registry);
+    if blocks.is_empty() {
+        return;
+    }
+    let index = blocks
+        .iter()
+        .position(|&block| block == editor.selected)
+        .unwrap_or_default();
+    let step = match delta < 0.0 {
+        true => 1,
+        false => blocks.len() - 1,
+    };
+    editor.selected = blocks[(index + step) % blocks.len()];
+}
+
+fn highlight_selected(editor: Res<BlockEditor>, mut slots: Query<(&HotbarSlot, &mut BorderColor)>) {
+    for (slot, mut border_color) in &mut slots {
+        border_color.0 = match slot.0 == editor.selected {
+            true => Color::WHITE,
+            false => Color::NONE,
+        };
+    }
+}
@@ -0,0 +1,218 @@
+mod hotbar;
+mod region;
+
+use crate::{
+    AppState,
+    camera_controller::CameraController,
+    key_bindings::KeyBindings,
+    screens::ScreenSetup,
+    world::{Block, BloxScene, BloxWorld, CurrentBlockRegistry, RaycastHit, Region},
+};
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_spawn_observer::SpawnObserver;
+
+/// How far away blocks can be edited, the far plane of the camera.
+const MAX_PICK_DISTANCE: f32 = 100.0;
+
+pub fn plugin(app: &mut App) {
+    app.add_plugins((hotbar::plugin, region::plugin));
+
+    // Setup and cleanup
+    app.add_systems(OnEnter(AppState::Game), setup.after(ScreenSetup));
+    app.add_systems(OnExit(AppState::Game), cleanup);
+
+    // Update
+    app.add_systems(
+        Update,
+        (select_hovered, undo_and_redo).run_if(in_state(AppState::Game)),
+    );
+}
+
+/// Editing state of the world.
+#[derive(Debug, Resource)]
+pub struct BlockEditor {
+    /// Block placed with the right mouse button, chosen in the hotbar.
+    pub selected: Block,
+    pub tool: EditorTool,
+    /// Corners picked with the region tool, see [`BlockEditor::region`].
+    pub region_corners: [Option<IVec3>; 2],
+    /// Blocks copied with the region tool, with the min corner at the origin.
+    pub clipboard: Option<BloxScene>,
+    /// What is under the cursor.
+    hovered: Option<RaycastHit>,
+    /// A drag started since the last button press, so the click is not meant as an edit.
+    is_dragged: bool,
+}
+
+impl Default for BlockEditor {
+    fn default() -> Self {
+        Self {
+            selected: Block::STONE,
+            tool: EditorTool::default(),
+            region_corners: [None; 2],
+            clipboard: None,
+            hovered: None,
+            is_dragged: false,
+        }
+    }
+}
+
+impl BlockEditor {
+    /// Region between the picked corners, once both are picked.
+    pub fn region(&self) -> Option<Region> {
+        match self.region_corners {
+            [Some(a), Some(b)] => Some(Region::from_corners(a, b)),
+            _ => None,
+        }
+    }
+}
+
+/// What clicking on the world does.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EditorTool {
+    /// Remove blocks with the left mouse button, place them with the right one.
+    #[default]
+    Block,
+    /// Pick the first region corner with the left mouse button, the second with the right one.
+    Region,
+}
+
+fn setup(mut commands: Commands, camera_controller: Single<Entity, With<CameraController>>) {
+    commands.init_resource::<BlockEditor>();
+
+    // Between the camera controller drag area and the rest of the UI, let events through so the
+    // camera can still be dragged
+    commands.spawn((
+        Name::new("Block Editor"),
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..Default::default()
+        },
+        GlobalZIndex(-5),
+        UiTargetCamera(*camera_controller),
+        StateScoped(AppState::Game),
+        Pickable {
+            should_block_lower: false,
+            is_hoverable: true,
+        },
+        Children::spawn((
+            SpawnObserver::new(
+                |_: Trigger<Pointer<Pressed>>, mut editor: ResMut<BlockEditor>| {
+                    editor.is_dragged = false;
+                },
+            ),
+            SpawnObserver::new(
+                |_: Trigger<Pointer<DragStart>>, mut editor: ResMut<BlockEditor>| {
+                    editor.is_dragged = true;
+                },
+            ),
+            SpawnObserver::new(edit_on_click),
+        )),
+    ));
+}
+
+fn cleanup(mut commands: Commands) {
+    commands.remove_resource::<BlockEditor>();
+}
+
+/// Select the block under the cursor, and the region of the region tool.
+fn select_hovered(
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&Camera, &GlobalTransform), With<CameraController>>,
+    mut editor: ResMut<BlockEditor>,
+    mut world: ResMut<BloxWorld>,
+    registry: Res<CurrentBlockRegistry>,
+) {
+    let (camera, camera_transform) = *camera;
+    let hovered = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor).ok())
+        .and_then(|ray| world.raycast(ray, MAX_PICK_DISTANCE, &registry));
+    // Changes every frame the cursor moves, nothing needs to react to it
+    editor.bypass_change_detection().hovered = hovered;
+
+    let mut selection = Vec::new();
+    if let Some(RaycastHit::Block(hit)) = hovered {
+        selection.push(Region::from_corners(hit.position, hit.position));
+    }
+    if editor.tool == EditorTool::Region {
+        selection.extend(region::highlighted(&editor));
+    }
+
+    // Only touch the world when the selection changes, the block shader is updated then
+    if world.selection() != selection {
+        world.set_selection(selection);
+    }
+}
+
+/// Edit the clicked block depending on the [`EditorTool`].
+fn edit_on_click(
+    trigger: Trigger<Pointer<Click>>,
+    mut editor: ResMut<BlockEditor>,
+    mut world: ResMut<BloxWorld>,
+    camera: Single<(&Camera, &GlobalTransform), With<CameraController>>,
+    registry: Res<CurrentBlockRegistry>,
+) {
+    if editor.is_dragged {
+        return;
+    }
+
+    let (camera, camera_transform) = *camera;
+    let Ok(ray) = camera.viewport_to_world(camera_transform, trigger.pointer_location.position)
+    else {
+        return;
+    };
+    let Some(hit) = world.raycast(ray, MAX_PICK_DISTANCE, &registry) else {
+        return;
+    };
+
+    match (editor.tool, trigger.button, hit) {
+        (EditorTool::Block, PointerButton::Primary, RaycastHit::Block(hit)) => {
+            world.set_block(hit.position, Block::AIR);
+        }
+        (EditorTool::Block, PointerButton::Secondary, _) => {
+            let pos = placement_position(hit, world.ground_height());
+            world.set_block(pos, editor.selected);
+        }
+        (EditorTool::Region, PointerButton::Primary | PointerButton::Secondary, _) => {
+            // The clicked block, or the space above the ground
+            let corner = match hit {
+                RaycastHit::Block(hit) => hit.position,
+                RaycastHit::Ground { .. } => placement_position(hit, world.ground_height()),
+            };
+            let index = match trigger.button {
+                PointerButton::Primary => 0,
+                _ => 1,
+            };
+            editor.region_corners[index] = Some(corner);
+        }
+        _ => (),
+    }
+}
+
+/// Where a block placed at `hit` goes, in front of the hit face or on the ground.
+fn placement_position(hit: RaycastHit, ground_height: i32) -> IVec3 {
+    match hit {
+        RaycastHit::Block(hit) => hit.position + hit.face.normal(),
+        RaycastHit::Ground { point, .. } => IVec3::new(
+            point.x.floor() as i32,
+            ground_height,
+            point.z.floor() as i32,
+        ),
+    }
+}
+
+fn undo_and_redo(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut world: ResMut<BloxWorld>,
+) {
+    if key_bindings.undo.just_pressed(&keyboard_input) {
+        if !world.undo() {
+            log::info!("Nothing to undo");
+        }
+    } else if key_bindings.redo.just_pressed(&keyboard_input) && !world.redo() {
+        log::info!("Nothing to redo");
+    }
+}
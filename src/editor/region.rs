@@ -0,0 +1,90 @@
+use super::{BlockEditor, EditorTool, placement_position};
+use crate::{
+    AppState,
+    key_bindings::{KeyBinding, KeyBindings},
+    world::{Axis, BloxWorld, RaycastHit, Region},
+};
+use bevy::prelude::*;
+
+pub fn plugin(app: &mut App) {
+    // Update
+    app.add_systems(Update, region_tool.run_if(in_state(AppState::Game)));
+}
+
+/// Blocks to highlight for the picked region.
+pub(super) fn highlighted(editor: &BlockEditor) -> Vec<Region> {
+    match editor.region() {
+        Some(region) => vec![region],
+        None => editor
+            .region_corners
+            .iter()
+            .flatten()
+            .map(|&corner| Region::from_corners(corner, corner))
+            .collect(),
+    }
+}
+
+fn region_tool(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut editor: ResMut<BlockEditor>,
+    mut world: ResMut<BloxWorld>,
+) {
+    let pressed = |binding: &KeyBinding| binding.just_pressed(&keyboard_input);
+
+    if pressed(&key_bindings.region_tool) {
+        editor.tool = match editor.tool {
+            EditorTool::Block => EditorTool::Region,
+            EditorTool::Region => EditorTool::Block,
+        };
+        log::info!("Switched to the {:?} tool", editor.tool);
+    }
+    if editor.tool != EditorTool::Region {
+        return;
+    }
+
+    // Transform the clipboard
+    if let Some(clipboard) = &editor.clipboard {
+        let transformed = if pressed(&key_bindings.clipboard_rotate) {
+            Some(clipboard.rotated(1))
+        } else if pressed(&key_bindings.clipboard_mirror_x) {
+            Some(clipboard.mirrored(Axis::X))
+        } else if pressed(&key_bindings.clipboard_mirror_z) {
+            Some(clipboard.mirrored(Axis::Z))
+        } else {
+            None
+        };
+        if transformed.is_some() {
+            editor.clipboard = transformed;
+        }
+    }
+
+    // Paste with the min corner at the hovered placement position
+    if pressed(&key_bindings.region_paste)
+        && let (Some(clipboard), Some(hovered)) = (&editor.clipboard, editor.hovered)
+    {
+        let offset = placement_position(hovered, world.ground_height());
+        world.paste(clipboard, offset);
+    }
+
+    let Some(region) = editor.region() else {
+        return;
+    };
+
+    if pressed(&key_bindings.region_fill) {
+        world.fill_region(region, editor.selected);
+    } else if pressed(&key_bindings.region_replace) {
+        // Replace the kind of block under the cursor
+        if let Some(RaycastHit::Block(hit)) = editor.hovered {
+            world.replace_in_region(region, hit.block, editor.selected);
+        }
+    } else if pressed(&key_bindings.region_hollow) {
+        world.hollow_region(region, editor.selected);
+    } else if pressed(&key_bindings.region_clear) {
+        world.clear_region(region);
+    } else if pressed(&key_bindings.region_copy) {
+        let clipboard = world.copy_region(region);
+        log::info!("Copied {} blocks", clipboard.blocks().count());
+        editor.clipboard = Some(clipboard);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{AppState, AssetsState, screens::ScreenSetup};
+use crate::{AppState, AssetsState, screens::ScreenSetup, world::BloxWorld};
 use bevy::{
     color::palettes::tailwind,
     pbr::{ExtendedMaterial, MaterialExtension},
@@ -20,8 +20,17 @@ pub fn plugin(app: &mut App) {
     app.configure_loading_state(
         LoadingStateConfig::new(AssetsState::Loading).load_collection::<GroundAssets>(),
     );
+
+    // Update
+    app.add_systems(
+        Update,
+        fit_to_world.run_if(in_state(AppState::Game).and(resource_exists_and_changed::<BloxWorld>)),
+    );
 }
 
+#[derive(Component)]
+struct Ground;
+
 #[derive(AssetCollection, Resource)]
 struct GroundAssets {
     #[expect(unused)] // Only place this here to ensure the shader is loaded
@@ -36,8 +45,9 @@ fn setup(
 ) {
     commands.spawn((
         Name::new("Ground"),
-        Transform::from_xyz(7.5, 0.0, 7.5),
-        Mesh3d(meshes.add(Plane3d::new(Vec3::Y, Vec2::splat(7.5)))),
+        Ground,
+        Visibility::Hidden,
+        Mesh3d(meshes.add(Plane3d::new(Vec3::Y, Vec2::splat(0.5)))),
         MeshMaterial3d(materials.add(ExtendedMaterial {
             base: StandardMaterial {
                 base_color: tailwind::GREEN_800.into(),
@@ -53,6 +63,23 @@ fn setup(
 
 fn cleanup(mut _commands: Commands) {}
 
+/// Stretch the ground under the blocks of the world, hide it if there are none.
+fn fit_to_world(
+    world: Res<BloxWorld>,
+    ground: Single<(&mut Transform, &mut Visibility), With<Ground>>,
+) {
+    let (mut transform, mut visibility) = ground.into_inner();
+    match world.bounds() {
+        Some((min, max)) => {
+            let (min, max) = (min.as_vec3(), max.as_vec3());
+            transform.translation = Vec3::new((min.x + max.x) / 2.0, min.y, (min.z + max.z) / 2.0);
+            transform.scale = Vec3::new(max.x - min.x, 1.0, max.z - min.z);
+            *visibility = Visibility::Inherited;
+        }
+        None => *visibility = Visibility::Hidden,
+    }
+}
+
 #[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
 struct GroundExtension {}
 
@@ -0,0 +1,191 @@
+use bevy::prelude::*;
+use std::fmt;
+
+pub fn plugin(app: &mut App) {
+    // Keep bindings inserted before the plugin was added
+    app.init_resource::<KeyBindings>();
+}
+
+/// A key together with the modifiers that have to be held. Other modifiers must not be held, so
+/// `1` and `Alt + 1` can be bound to different actions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    pub key: KeyCode,
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+}
+
+impl KeyBinding {
+    pub const fn new(key: KeyCode) -> Self {
+        Self {
+            key,
+            shift: false,
+            control: false,
+            alt: false,
+        }
+    }
+
+    pub const fn with_shift(mut self) -> Self {
+        self.shift = true;
+        self
+    }
+
+    pub const fn with_control(mut self) -> Self {
+        self.control = true;
+        self
+    }
+
+    pub const fn with_alt(mut self) -> Self {
+        self.alt = true;
+        self
+    }
+
+    pub fn just_pressed(&self, input: &ButtonInput<KeyCode>) -> bool {
+        input.just_pressed(self.key) && modifiers_pressed(input, self.shift, self.control, self.alt)
+    }
+}
+
+/// Shown like `Ctrl+Shift+S`, for hints in the game.
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.control, "Ctrl"),
+            (self.shift, "Shift"),
+            (self.alt, "Alt"),
+        ] {
+            if held {
+                write!(f, "{name}+")?;
+            }
+        }
+        let key = format!("{:?}", self.key);
+        let key = key
+            .strip_prefix("Key")
+            .or_else(|| key.strip_prefix("Digit"))
+            .unwrap_or(&key);
+        write!(f, "{key}")
+    }
+}
+
+/// Modifiers that have to be held while scrolling the mouse wheel, other modifiers must not be
+/// held like for a [`KeyBinding`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScrollBinding {
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+}
+
+impl ScrollBinding {
+    pub const fn new() -> Self {
+        Self {
+            shift: false,
+            control: false,
+            alt: false,
+        }
+    }
+
+    pub const fn with_shift(mut self) -> Self {
+        self.shift = true;
+        self
+    }
+
+    pub const fn with_control(mut self) -> Self {
+        self.control = true;
+        self
+    }
+
+    pub const fn with_alt(mut self) -> Self {
+        self.alt = true;
+        self
+    }
+
+    pub fn pressed(&self, input: &ButtonInput<KeyCode>) -> bool {
+        modifiers_pressed(input, self.shift, self.control, self.alt)
+    }
+}
+
+fn modifiers_pressed(input: &ButtonInput<KeyCode>, shift: bool, control: bool, alt: bool) -> bool {
+    input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) == shift
+        && input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) == control
+        && input.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) == alt
+}
+
+/// Keys of the game actions. Insert this resource before adding the plugin to change them.
+#[derive(Debug, Clone, Resource)]
+pub struct KeyBindings {
+    pub ray_tracer_disabled: KeyBinding,
+    pub ray_tracer_continuous: KeyBinding,
+    pub ray_tracer_single_frame: KeyBinding,
+    pub ray_tracer_progressive: KeyBinding,
+    pub ray_tracer_cancel: KeyBinding,
+    pub ray_tracer_toggle_transparent: KeyBinding,
+    pub ray_tracer_toggle_integrator: KeyBinding,
+    /// Save the last rendered frame to the `renders` folder.
+    pub ray_tracer_save: KeyBinding,
+    /// Save the world to `saves/world.blox`.
+    pub quick_save: KeyBinding,
+    /// Replace the world with `saves/world.blox`.
+    pub quick_load: KeyBinding,
+    pub undo: KeyBinding,
+    pub redo: KeyBinding,
+    /// Switch between placing single blocks and editing regions.
+    pub region_tool: KeyBinding,
+    pub region_fill: KeyBinding,
+    /// Replace the kind of block under the cursor with the selected block.
+    pub region_replace: KeyBinding,
+    pub region_hollow: KeyBinding,
+    pub region_clear: KeyBinding,
+    pub region_copy: KeyBinding,
+    /// Paste the clipboard in front of the block under the cursor.
+    pub region_paste: KeyBinding,
+    pub clipboard_rotate: KeyBinding,
+    pub clipboard_mirror_x: KeyBinding,
+    pub clipboard_mirror_z: KeyBinding,
+    /// Select the block of a hotbar slot, in slot order.
+    pub hotbar_slots: Vec<KeyBinding>,
+    /// Scrolling anywhere with these modifiers selects the next or previous hotbar slot instead
+    /// of zooming the camera.
+    pub hotbar_scroll: ScrollBinding,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            ray_tracer_disabled: KeyBinding::new(KeyCode::Digit1).with_alt(),
+            ray_tracer_continuous: KeyBinding::new(KeyCode::Digit2).with_alt(),
+            ray_tracer_single_frame: KeyBinding::new(KeyCode::Digit3).with_alt(),
+            ray_tracer_progressive: KeyBinding::new(KeyCode::Digit4).with_alt(),
+            ray_tracer_cancel: KeyBinding::new(KeyCode::Escape),
+            ray_tracer_toggle_transparent: KeyBinding::new(KeyCode::KeyT),
+            ray_tracer_toggle_integrator: KeyBinding::new(KeyCode::KeyP),
+            ray_tracer_save: KeyBinding::new(KeyCode::F2),
+            quick_save: KeyBinding::new(KeyCode::F5),
+            quick_load: KeyBinding::new(KeyCode::F9),
+            undo: KeyBinding::new(KeyCode::KeyZ).with_control(),
+            redo: KeyBinding::new(KeyCode::KeyZ).with_control().with_shift(),
+            region_tool: KeyBinding::new(KeyCode::Tab),
+            region_fill: KeyBinding::new(KeyCode::KeyF),
+            region_replace: KeyBinding::new(KeyCode::KeyR),
+            region_hollow: KeyBinding::new(KeyCode::KeyH),
+            region_clear: KeyBinding::new(KeyCode::Delete),
+            region_copy: KeyBinding::new(KeyCode::KeyC).with_control(),
+            region_paste: KeyBinding::new(KeyCode::KeyV).with_control(),
+            clipboard_rotate: KeyBinding::new(KeyCode::KeyR).with_control(),
+            clipboard_mirror_x: KeyBinding::new(KeyCode::KeyM).with_control(),
+            clipboard_mirror_z: KeyBinding::new(KeyCode::KeyM).with_control().with_shift(),
+            hotbar_slots: vec![
+                KeyBinding::new(KeyCode::Digit1),
+                KeyBinding::new(KeyCode::Digit2),
+                KeyBinding::new(KeyCode::Digit3),
+                KeyBinding::new(KeyCode::Digit4),
+                KeyBinding::new(KeyCode::Digit5),
+                KeyBinding::new(KeyCode::Digit6),
+                KeyBinding::new(KeyCode::Digit7),
+                KeyBinding::new(KeyCode::Digit8),
+                KeyBinding::new(KeyCode::Digit9),
+            ],
+            hotbar_scroll: ScrollBinding::new().with_shift(),
+        }
+    }
+}
@@ -1,5 +1,7 @@
 mod camera_controller;
+mod editor;
 mod ground;
+mod key_bindings;
 mod ray_tracer;
 mod screens;
 mod util;
@@ -8,7 +10,25 @@ mod world;
 use bevy::{asset::AssetMetaCheck, prelude::*};
 use bevy_asset_loader::loading_state::{LoadingState, LoadingStateAppExt};
 
-pub use self::world::{Block, BloxScene, BloxWorld};
+pub use self::{
+    key_bindings::{KeyBinding, KeyBindings, ScrollBinding},
+    ray_tracer::{
+        BlockTextures, LuxScene, RenderedFrame, ambient_light, directional_light, point_light,
+        save_frame,
+    },
+    world::{
+        Axis, Block, BlockDefinition, BlockFace, BlockHit, BlockMaterial, BlockRegistry,
+        BlockRegistryError, BloxScene, BloxWorld, CurrentBlockRegistry, DropReason, DroppedBlock,
+        DroppedVoxel, RaycastHit, Region, SceneFileError, SchematicImport, SchematicImportOptions,
+        VoxExport, VoxImport, VoxImportOptions, VoxMapping, VoxOversize, default_scene,
+    },
+};
+
+/// Sky color, used as clear color and as ray tracer background.
+pub const SKY_COLOR: Color = Color::srgb(0.5, 0.5, 1.0);
+
+/// Radius of the point light of the game screen, for soft shadows.
+pub const POINT_LIGHT_RADIUS: f32 = 0.25;
 
 pub struct BloxPlugin;
 
@@ -33,7 +53,7 @@ impl Plugin for BloxPlugin {
                     ..default()
                 }),
         )
-        .insert_resource(ClearColor(Color::srgb(0.5, 0.5, 1.0)))
+        .insert_resource(ClearColor(SKY_COLOR))
         .insert_resource(AmbientLight {
             brightness: 80.0,
             ..default()
@@ -52,6 +72,8 @@ impl Plugin for BloxPlugin {
             ground::plugin,
             world::plugin,
             camera_controller::plugin,
+            editor::plugin,
+            key_bindings::plugin,
             ray_tracer::plugin,
             util::plugin,
         ));
@@ -0,0 +1,635 @@
+mod scene;
+
+pub use self::scene::{BlockTextures, LuxScene, ambient_light, directional_light, point_light};
+
+use crate::{
+    AppState, AssetsState,
+    key_bindings::KeyBindings,
+    screens::ScreenSetup,
+    world::{BlockRegistryChanged, BloxWorld, CurrentBlockRegistry},
+};
+use bevy::{
+    asset::RenderAssetUsages,
+    platform::time::Instant,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    tasks::{AsyncComputeTaskPool, Task},
+    window::PrimaryWindow,
+};
+use bevy_asset_loader::loading_state::config::LoadingStateConfig;
+use bevy_asset_loader::prelude::*;
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub fn plugin(app: &mut App) {
+    // Setup and cleanup
+    app.add_systems(OnEnter(AppState::Game), setup.after(ScreenSetup));
+    app.add_systems(OnExit(AppState::Game), cleanup);
+
+    // Assets
+    app.configure_loading_state(
+        LoadingStateConfig::new(AssetsState::Loading).finally_init_resource::<BlockTextures>(),
+    );
+
+    // Update
+    app.init_resource::<RenderedFrame>();
+    app.add_systems(
+        Update,
+        reload_block_textures.run_if(on_event::<BlockRegistryChanged>),
+    );
+    app.add_systems(
+        PostUpdate,
+        update
+            .after(TransformSystem::TransformPropagate)
+            .run_if(in_state(AppState::Game)),
+    );
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_systems(
+        PostUpdate,
+        save_on_key.after(update).run_if(in_state(AppState::Game)),
+    );
+}
+
+fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    commands.spawn((
+        Name::new("Ray Tracer"),
+        Node {
+            display: Display::None,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..default()
+        },
+        ImageNode::new(images.add(Image::transparent())),
+        Pickable::IGNORE,
+        StateScoped(AppState::Game),
+    ));
+    commands.spawn((
+        Name::new("Ray Tracer Progress"),
+        RenderProgress,
+        Text::default(),
+        TextFont {
+            font_size: 12.0,
+            ..default()
+        },
+        Pickable::IGNORE,
+        GlobalZIndex(1000),
+        Node {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            top: Val::Px(4.0),
+            left: Val::Px(4.0),
+            ..default()
+        },
+        StateScoped(AppState::Game),
+    ));
+}
+
+fn cleanup(mut _commands: Commands) {}
+
+/// Render with the materials and textures of the changed block registry from now on.
+fn reload_block_textures(
+    mut commands: Commands,
+    images: Res<Assets<Image>>,
+    registry: Res<CurrentBlockRegistry>,
+) {
+    commands.insert_resource(BlockTextures::from_images(registry.0.clone(), &images));
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum RenderMode {
+    Disabled,
+    #[default]
+    Continuous,
+    SingleFrame,
+    Progressive,
+}
+
+/// Samples accumulated over multiple frames in [`RenderMode::Progressive`]. Reset whenever any of
+/// the inputs of the render or the block textures change.
+#[derive(Debug, Default)]
+struct Accumulation {
+    inputs: Option<AccumulationInputs>,
+    samples: u32,
+    pixels: Vec<lux::LinearRgb>,
+}
+
+#[derive(Debug, PartialEq)]
+struct AccumulationInputs {
+    integrator: lux::Integrator,
+    camera: lux::Camera,
+    dimensions: UVec2,
+    scene: SceneInputs,
+}
+
+/// Everything a [`LuxScene`] is built from. The scene is only built again when these change.
+#[derive(Debug, Clone, PartialEq)]
+struct SceneInputs {
+    /// See [`BloxWorld::generation`].
+    world_generation: u64,
+    lights: Vec<lux::Light>,
+    shadow_samples: u32,
+}
+
+fn update(
+    mut mode: Local<RenderMode>,
+    mut transparent: Local<bool>,
+    mut integrator: Local<lux::Integrator>,
+    mut accumulation: Local<Accumulation>,
+    (mut job, mut cached_scene): (
+        Local<Option<RenderJob>>,
+        Local<Option<(SceneInputs, Arc<LuxScene>)>>,
+    ),
+
+    mut image: Single<(&mut Node, &mut ImageNode), Without<RenderProgress>>,
+    mut progress: Single<(&mut Node, &mut Text), With<RenderProgress>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    camera: Single<(&GlobalTransform, &Projection), With<Camera3d>>,
+    (directional_lights, point_lights): (
+        Query<&GlobalTransform, With<DirectionalLight>>,
+        Query<(&GlobalTransform, &PointLight)>,
+    ),
+    clear_color: Res<ClearColor>,
+    mut images: ResMut<Assets<Image>>,
+    mut frame: ResMut<RenderedFrame>,
+    world: Res<BloxWorld>,
+    block_textures: Res<BlockTextures>,
+    (keyboard_input, key_bindings): (Res<ButtonInput<KeyCode>>, Res<KeyBindings>),
+) {
+    let mut rebuild = false;
+
+    if key_bindings
+        .ray_tracer_disabled
+        .just_pressed(&keyboard_input)
+    {
+        *mode = RenderMode::Disabled;
+    } else if key_bindings
+        .ray_tracer_continuous
+        .just_pressed(&keyboard_input)
+    {
+        *mode = RenderMode::Continuous;
+    } else if key_bindings
+        .ray_tracer_single_frame
+        .just_pressed(&keyboard_input)
+    {
+        *mode = RenderMode::SingleFrame;
+        rebuild = true;
+    } else if key_bindings
+        .ray_tracer_progressive
+        .just_pressed(&keyboard_input)
+    {
+        *mode = RenderMode::Progressive;
+    }
+
+    if key_bindings
+        .ray_tracer_toggle_transparent
+        .just_pressed(&keyboard_input)
+    {
+        *transparent = !*transparent;
+        rebuild = true;
+    }
+
+    if key_bindings
+        .ray_tracer_toggle_integrator
+        .just_pressed(&keyboard_input)
+    {
+        *integrator = match *integrator {
+            lux::Integrator::Whitted => lux::Integrator::PathTracing,
+            lux::Integrator::PathTracing => lux::Integrator::Whitted,
+        };
+        rebuild = true;
+    }
+
+    let lux_camera = lux::Camera {
+        translation: camera.0.translation(),
+        direction: camera.0.forward(),
+        up: Dir3::Y,
+        fov: match camera.1 {
+            Projection::Perspective(p) => p.fov,
+            _ => PerspectiveProjection::default().fov,
+        },
+        background: (**clear_color).into(),
+    };
+
+    // Cancel a running render if the mode changes, and a single frame if the camera moves or on
+    // request. Progressive passes are replaced below when their inputs change.
+    if let Some(running) = &*job {
+        let cancel = running.mode != *mode
+            || (*mode == RenderMode::SingleFrame
+                && (key_bindings.ray_tracer_cancel.just_pressed(&keyboard_input)
+                    || camera_moved(&running.camera, &lux_camera)));
+        if cancel {
+            if running.mode == RenderMode::SingleFrame {
+                log::info!("Render cancelled");
+            }
+            *job = None;
+            if *mode == RenderMode::SingleFrame {
+                *mode = RenderMode::Disabled;
+            }
+        }
+    }
+
+    // The block textures are replaced when the block registry changes
+    if block_textures.is_changed() {
+        *cached_scene = None;
+    }
+    if *mode != RenderMode::Progressive || block_textures.is_changed() {
+        *accumulation = Accumulation::default();
+    }
+
+    if *mode == RenderMode::Disabled {
+        image.0.display = Display::None;
+        progress.0.display = Display::None;
+        return;
+    }
+    image.0.display = Display::DEFAULT;
+
+    let (scale, samples_per_pixel, shadow_samples) = match *mode {
+        RenderMode::SingleFrame => (1, 4, 4),
+        RenderMode::Continuous => (4, 1, 1),
+        RenderMode::Progressive => (1, 1, 1),
+        RenderMode::Disabled => unreachable!(),
+    };
+    let dimensions = window.physical_size() / scale;
+    let scene_inputs = SceneInputs {
+        world_generation: world.generation(),
+        lights: directional_lights
+            .iter()
+            .map(|transform| directional_light(transform.forward(), shadow_samples))
+            .chain(point_lights.iter().map(|(transform, light)| {
+                point_light(transform.translation(), light.radius, shadow_samples)
+            }))
+            .chain([ambient_light()])
+            .collect(),
+        shadow_samples,
+    };
+
+    match *mode {
+        RenderMode::Continuous => rebuild = true,
+        // Start over if anything changed, and render the next pass once the last one is done
+        RenderMode::Progressive => {
+            let inputs = AccumulationInputs {
+                integrator: *integrator,
+                camera: lux_camera,
+                dimensions,
+                scene: scene_inputs.clone(),
+            };
+            if accumulation.inputs.as_ref() != Some(&inputs) {
+                *accumulation = Accumulation {
+                    inputs: Some(inputs),
+                    samples: 0,
+                    pixels: vec![lux::LinearRgb::BLACK; (dimensions.x * dimensions.y) as usize],
+                };
+                *job = None;
+            }
+            rebuild |= job.is_none();
+        }
+        RenderMode::SingleFrame | RenderMode::Disabled => (),
+    }
+
+    if rebuild {
+        let scene = match &*cached_scene {
+            Some((inputs, scene)) if *inputs == scene_inputs => scene.clone(),
+            _ => {
+                let scene = Arc::new(LuxScene::new(
+                    scene_inputs.lights.clone(),
+                    world.to_scene(),
+                    block_textures.clone(),
+                    shadow_samples,
+                ));
+                *cached_scene = Some((scene_inputs, scene.clone()));
+                scene
+            }
+        };
+        let renderer = lux::Renderer::init(lux_camera, dimensions)
+            .with_integrator(*integrator)
+            .with_samples_per_pixel(samples_per_pixel)
+            .with_jitter(*mode == RenderMode::Progressive)
+            .with_seed(accumulation.samples as u64);
+
+        // Full resolution frames and passes are rendered in the background and streamed in tile
+        // by tile, only the small continuous frames are rendered right away
+        if *mode == RenderMode::Continuous {
+            let pixels = renderer.render(&*scene);
+
+            *frame = RenderedFrame {
+                dimensions,
+                pixels: pixels.iter().map(|p| lux::LinearRgb::from(*p)).collect(),
+            };
+
+            *images.get_mut(&image.1.image).unwrap() = Image::new(
+                Extent3d {
+                    width: dimensions.x,
+                    height: dimensions.y,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                pixels
+                    .into_iter()
+                    .flat_map(|p| to_rgba8(p, *transparent))
+                    .collect(),
+                TextureFormat::bevy_default(),
+                RenderAssetUsages::default(),
+            );
+        } else {
+            // Progressive passes are drawn over the last image of the same size
+            let size = images.get(&image.1.image).unwrap().size();
+            if *mode == RenderMode::SingleFrame || size != dimensions {
+                *images.get_mut(&image.1.image).unwrap() = Image::new_fill(
+                    Extent3d {
+                        width: dimensions.x,
+                        height: dimensions.y,
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    &[0, 0, 0, 0],
+                    TextureFormat::bevy_default(),
+                    RenderAssetUsages::default(),
+                );
+            }
+            *job = Some(RenderJob::spawn(*mode, renderer, scene, samples_per_pixel));
+        }
+    }
+
+    // Stream finished tiles of the render into the image
+    let Some(running) = &mut *job else {
+        progress.0.display = Display::None;
+        return;
+    };
+
+    // Passes after the first one only show up once they are added to the accumulated samples
+    let stream = running.mode == RenderMode::SingleFrame || accumulation.samples == 0;
+    let image = images.get_mut(&image.1.image).unwrap();
+    let data = image.data.as_mut().unwrap();
+    for RenderedTile { tile, pixels } in running.poll() {
+        for (y, row) in pixels.chunks(tile.size.x as usize).enumerate() {
+            let offset = ((tile.min.y + y as u32) * running.dimensions.x + tile.min.x) as usize * 4;
+            for (x, pixel) in row.iter().enumerate() {
+                if stream {
+                    data[offset + x * 4..][..4].copy_from_slice(&to_rgba8(*pixel, *transparent));
+                }
+                running.pixels[offset / 4 + x] = lux::LinearRgb::from(*pixel);
+            }
+        }
+        running.finished_pixels += pixels.len();
+    }
+
+    let pixel_count = (running.dimensions.x * running.dimensions.y) as usize;
+    if running.finished_pixels < pixel_count {
+        if running.mode == RenderMode::SingleFrame {
+            progress.0.display = Display::DEFAULT;
+            progress.1.0 = format!(
+                "Rendering {}% ({} to cancel)",
+                100 * running.finished_pixels / pixel_count,
+                key_bindings.ray_tracer_cancel
+            );
+        }
+        return;
+    }
+
+    let pixels = std::mem::take(&mut running.pixels);
+    let pixels = match running.mode {
+        RenderMode::Progressive => {
+            // Add the pass to the accumulated samples
+            let accumulation = &mut *accumulation;
+            accumulation.samples += running.samples_per_pixel;
+            let weight = running.samples_per_pixel as f32 / accumulation.samples as f32;
+            for (accumulated, pixel) in accumulation.pixels.iter_mut().zip(&pixels) {
+                *accumulated = accumulated.mix(pixel, weight);
+            }
+            for (texel, pixel) in data.chunks_exact_mut(4).zip(&accumulation.pixels) {
+                texel.copy_from_slice(&to_rgba8((*pixel).into(), *transparent));
+            }
+            accumulation.pixels.clone()
+        }
+        _ => {
+            log::info!("Rendered in {:?}", running.start.elapsed());
+            pixels
+        }
+    };
+    *frame = RenderedFrame {
+        dimensions: running.dimensions,
+        pixels,
+    };
+    *job = None;
+    progress.0.display = Display::None;
+}
+
+/// The last completely rendered frame in linear color, before any clamping.
+#[derive(Debug, Default, Resource)]
+pub struct RenderedFrame {
+    pub dimensions: UVec2,
+    pub pixels: Vec<lux::LinearRgb>,
+}
+
+/// Save a rendered frame to `path`. The format is picked from the extension: `.png` is written
+/// as 8-bit sRGB, `.exr` and `.hdr` keep the linear HDR values.
+pub fn save_frame(frame: &RenderedFrame, path: &Path) -> Result<(), image::ImageError> {
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    let is_png = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+    if is_png {
+        let data = frame
+            .pixels
+            .iter()
+            .flat_map(|p| Srgba::from(LinearRgba::from(*p)).to_u8_array_no_alpha())
+            .collect();
+        image::RgbImage::from_raw(frame.dimensions.x, frame.dimensions.y, data)
+            .unwrap()
+            .save(path)
+    } else {
+        let data = frame
+            .pixels
+            .iter()
+            .flat_map(|p| [p.red, p.green, p.blue])
+            .collect();
+        image::Rgb32FImage::from_raw(frame.dimensions.x, frame.dimensions.y, data)
+            .unwrap()
+            .save(path)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_on_key(
+    frame: Res<RenderedFrame>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+) {
+    if !key_bindings.ray_tracer_save.just_pressed(&keyboard_input) {
+        return;
+    }
+
+    if frame.pixels.is_empty() {
+        log::warn!("No rendered frame to save");
+        return;
+    }
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    for extension in ["png", "exr"] {
+        let path = format!("renders/render-{timestamp}.{extension}");
+        match save_frame(&frame, Path::new(&path)) {
+            Ok(()) => log::info!("Saved render to {path}"),
+            Err(err) => log::error!("Failed to save render to {path}: {err}"),
+        }
+    }
+}
+
+fn to_rgba8(color: Color, transparent: bool) -> [u8; 4] {
+    if transparent {
+        color.to_srgba().with_alpha(0.5).to_u8_array()
+    } else {
+        color.to_srgba().with_alpha(1.0).to_u8_array()
+    }
+}
+
+fn camera_moved(a: &lux::Camera, b: &lux::Camera) -> bool {
+    a.translation.distance_squared(b.translation) > 1e-6 || a.direction.dot(*b.direction) < 0.99999
+}
+
+#[derive(Component)]
+struct RenderProgress;
+
+/// Time spent per frame on incremental renders, see [`RenderWork::Incremental`].
+const INCREMENTAL_FRAME_BUDGET: Duration = Duration::from_millis(10);
+
+/// A single frame or progressive pass that is rendered over multiple frames of the app. Finished
+/// parts are picked up by [`update`] through [`RenderJob::poll`]. Dropping the job cancels it.
+struct RenderJob {
+    mode: RenderMode,
+    samples_per_pixel: u32,
+    camera: lux::Camera,
+    dimensions: UVec2,
+    pixels: Vec<lux::LinearRgb>,
+    finished_pixels: usize,
+    start: Instant,
+    work: RenderWork,
+}
+
+enum RenderWork {
+    /// Workers on the async compute task pool pull tiles from a shared queue.
+    Background {
+        finished: Arc<Mutex<Vec<RenderedTile>>>,
+        queue: Arc<lux::TileQueue>,
+        _tasks: Vec<Task<()>>,
+    },
+    /// Batches of rows are rendered on the main thread each frame. Used on wasm, where tasks on
+    /// the task pool would block the main thread until the whole frame is done.
+    Incremental {
+        renderer: lux::Renderer,
+        scene: Arc<LuxScene>,
+        next_row: u32,
+    },
+}
+
+struct RenderedTile {
+    tile: lux::Tile,
+    pixels: Vec<Color>,
+}
+
+impl RenderJob {
+    fn spawn(
+        mode: RenderMode,
+        renderer: lux::Renderer,
+        scene: Arc<LuxScene>,
+        samples_per_pixel: u32,
+    ) -> Self {
+        let camera = *renderer.camera();
+        let dimensions = renderer.dimensions();
+
+        let work = if cfg!(target_arch = "wasm32") {
+            RenderWork::Incremental {
+                renderer,
+                scene,
+                next_row: 0,
+            }
+        } else {
+            let finished = Arc::new(Mutex::new(Vec::new()));
+            let queue = Arc::new(lux::TileQueue::new());
+            let renderer = Arc::new(renderer);
+
+            let pool = AsyncComputeTaskPool::get();
+            let tasks = (0..pool.thread_num().max(1))
+                .map(|_| {
+                    let finished = finished.clone();
+                    let queue = queue.clone();
+                    let renderer = renderer.clone();
+                    let scene = scene.clone();
+                    pool.spawn(async move {
+                        renderer.render_queued_tiles(&*scene, &queue, |tile, pixels| {
+                            finished.lock().unwrap().push(RenderedTile {
+                                tile,
+                                pixels: pixels.to_vec(),
+                            });
+                        });
+                    })
+                })
+                .collect();
+
+            RenderWork::Background {
+                finished,
+                queue,
+                _tasks: tasks,
+            }
+        };
+
+        Self {
+            mode,
+            samples_per_pixel,
+            camera,
+            dimensions,
+            pixels: vec![lux::LinearRgb::BLACK; (dimensions.x * dimensions.y) as usize],
+            finished_pixels: 0,
+            start: Instant::now(),
+            work,
+        }
+    }
+
+    fn poll(&mut self) -> Vec<RenderedTile> {
+        match &mut self.work {
+            RenderWork::Background { finished, .. } => {
+                std::mem::take(&mut *finished.lock().unwrap())
+            }
+            RenderWork::Incremental {
+                renderer,
+                scene,
+                next_row,
+            } => {
+                let start = Instant::now();
+                let mut tiles = Vec::new();
+                while *next_row < self.dimensions.y && start.elapsed() < INCREMENTAL_FRAME_BUDGET {
+                    let mut pixels = vec![Color::BLACK; self.dimensions.x as usize];
+                    renderer.render_rows(&**scene, *next_row..*next_row + 1, &mut pixels);
+                    tiles.push(RenderedTile {
+                        tile: lux::Tile {
+                            min: UVec2::new(0, *next_row),
+                            size: UVec2::new(self.dimensions.x, 1),
+                        },
+                        pixels,
+                    });
+                    *next_row += 1;
+                }
+                tiles
+            }
+        }
+    }
+}
+
+impl Drop for RenderJob {
+    fn drop(&mut self) {
+        if let RenderWork::Background { queue, .. } = &self.work {
+            queue.cancel();
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use crate::world::{Block, BlockFace, BlockMaterial, BlockRegistry, BloxScene, WorldAssets};
+use bevy::{prelude::*, render::render_resource::TextureFormat};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Resource)]
+pub struct BlockTextures {
+    registry: Arc<BlockRegistry>,
+    /// Indexed by texture layer.
+    textures: Arc<[BlockTexture]>,
+}
+
+impl BlockTextures {
+    fn sample(&self, block: Block, face: BlockFace, uv: Vec2) -> lux::Material {
+        fn reflective(albedo: impl Into<lux::LinearRgb>, reflectivity: f32) -> lux::Material {
+            lux::Material::Reflective {
+                albedo: albedo.into(),
+                reflectivity,
+            }
+        }
+        fn refractive(
+            albedo: impl Into<lux::LinearRgb>,
+            index: f32,
+            transparency: f32,
+        ) -> lux::Material {
+            lux::Material::Refractive {
+                albedo: albedo.into(),
+                index,
+                transparency,
+            }
+        }
+
+        fn microfacet(
+            base_color: impl Into<lux::LinearRgb>,
+            material: &BlockMaterial,
+        ) -> lux::Material {
+            lux::Material::Microfacet {
+                base_color: base_color.into(),
+                roughness: material.roughness,
+                metallic: material.metallic,
+                reflectance: material.reflectance,
+            }
+        }
+
+        let definition = self.registry.definition(block);
+        let material = &definition.material;
+        if let Some(radiance) = emission(&material.emission) {
+            return lux::Material::Emissive { radiance };
+        }
+        let color = self.textures[definition.albedo_layer(face.normal())].sample(uv);
+
+        // Only texels that aren't opaque reflect or refract, see the registry file
+        let coverage = 1.0 - color.alpha;
+        if coverage <= 0.0 {
+            microfacet(color, material)
+        } else if material.transparency > 0.0 {
+            refractive(
+                color,
+                material.index_of_refraction,
+                material.transparency * coverage,
+            )
+        } else if material.reflectivity > 0.0 {
+            reflective(color, material.reflectivity * coverage)
+        } else {
+            microfacet(color, material)
+        }
+    }
+}
+
+impl BlockTextures {
+    /// Build the textures of the blocks of `registry` from sRGB RGBA8 images, given in the order
+    /// of [`BlockRegistry::texture_paths`].
+    pub fn from_rgba8<'a>(
+        registry: Arc<BlockRegistry>,
+        images: impl IntoIterator<Item = (UVec2, &'a [u8])>,
+    ) -> Self {
+        let mut textures = Vec::new();
+
+        for (size, data) in images {
+            textures.push(BlockTexture {
+                size,
+                data: data
+                    .chunks(4)
+                    .map(|chunk| {
+                        LinearRgba::from(Srgba::new(
+                            chunk[0] as f32 / 255.0,
+                            chunk[1] as f32 / 255.0,
+                            chunk[2] as f32 / 255.0,
+                            chunk[3] as f32 / 255.0,
+                        ))
+                    })
+                    .collect(),
+            });
+        }
+
+        Self {
+            registry,
+            textures: textures.into(),
+        }
+    }
+}
+
+impl FromWorld for BlockTextures {
+    fn from_world(world: &mut World) -> Self {
+        let world_assets = world.resource::<WorldAssets>();
+        let registry = world.resource::<Assets<BlockRegistry>>();
+        let registry = Arc::new(registry.get(&world_assets.block_registry).unwrap().clone());
+        Self::from_images(registry, world.resource::<Assets<Image>>())
+    }
+}
+
+impl BlockTextures {
+    /// Build the textures of the blocks of `registry` from the loaded images of the registry,
+    /// which must have passed [`BlockRegistry::validate_images`].
+    pub(super) fn from_images(registry: Arc<BlockRegistry>, images: &Assets<Image>) -> Self {
+        Self::from_rgba8(
+            registry.clone(),
+            registry.images().iter().map(|handle| {
+                let image = images.get(handle).unwrap();
+
+                assert_eq!(
+                    image.texture_descriptor.format,
+                    TextureFormat::Rgba8UnormSrgb
+                );
+
+                (image.size(), image.data.as_deref().unwrap())
+            }),
+        )
+    }
+}
+
+/// Emitted radiance of a block material, `None` if it doesn't emit light.
+fn emission(&[red, green, blue]: &[f32; 3]) -> Option<lux::LinearRgb> {
+    (red > 0.0 || green > 0.0 || blue > 0.0).then(|| lux::LinearRgb::new(red, green, blue))
+}
+
+#[derive(Debug)]
+struct BlockTexture {
+    size: UVec2,
+    data: Vec<LinearRgba>,
+}
+
+impl BlockTexture {
+    fn sample(&self, uv: Vec2) -> LinearRgba {
+        let uv = uv.fract();
+        let u = (uv.x * self.size.x as f32).clamp(0.0, self.size.x as f32 - 1.0) as u32;
+        let v = (uv.y * self.size.y as f32).clamp(0.0, self.size.y as f32 - 1.0) as u32;
+        self.data[(v * self.size.x + u) as usize]
+    }
+}
+
+/// A [`BloxScene`] with lights, ready to be rendered by [`lux::Renderer`].
+#[derive(Debug)]
+pub struct LuxScene {
+    lights: Vec<lux::Light>,
+    /// A box light for every emitting block.
+    emitters: lux::Emitters,
+    scene: BloxScene,
+    bounds: Option<(IVec3, IVec3)>,
+    textures: BlockTextures,
+}
+
+impl LuxScene {
+    /// The emitting blocks of `scene` become box lights with `emitter_shadow_samples` shadow rays.
+    pub fn new(
+        lights: Vec<lux::Light>,
+        scene: BloxScene,
+        textures: BlockTextures,
+        emitter_shadow_samples: u32,
+    ) -> Self {
+        let emitters = lux::Emitters::new(
+            scene
+                .blocks()
+                .filter_map(|(pos, block)| {
+                    let material = &textures.registry.definition(block).material;
+                    Some(lux::Light::Box {
+                        min: pos.as_vec3(),
+                        max: (pos + 1).as_vec3(),
+                        color: emission(&material.emission)?,
+                        intensity: 1.0,
+                        shadow_samples: emitter_shadow_samples,
+                    })
+                })
+                .collect(),
+        );
+
+        Self {
+            lights,
+            emitters,
+            bounds: scene.bounds(),
+            scene,
+            textures,
+        }
+    }
+}
+
+/// Angular diameter of the sun seen from the earth, in radians.
+const SUN_ANGULAR_DIAMETER: f32 = 0.0093;
+
+pub fn directional_light(direction: Dir3, shadow_samples: u32) -> lux::Light {
+    lux::Light::Directional {
+        direction,
+        angular_diameter: SUN_ANGULAR_DIAMETER,
+        color: lux::LinearRgb::WHITE,
+        intensity: 5.0,
+        shadow_samples,
+    }
+}
+
+pub fn point_light(position: Vec3, radius: f32, shadow_samples: u32) -> lux::Light {
+    lux::Light::Point {
+        position,
+        radius,
+        color: lux::LinearRgb::WHITE,
+        intensity: 400.0,
+        shadow_samples,
+    }
+}
+
+pub fn ambient_light() -> lux::Light {
+    lux::Light::Ambient {
+        color: lux::LinearRgb::WHITE,
+        intensity: 0.05,
+    }
+}
+
+impl lux::Scene for LuxScene {
+    fn lights(&self) -> &[lux::Light] {
+        &self.lights
+    }
+
+    fn emitters(&self) -> &lux::Emitters {
+        &self.emitters
+    }
+
+    fn cast_ray(&self, ray: Ray3d, max_distance: f32) -> Option<lux::RayHit> {
+        let hit = self
+            .scene
+            .cast_ray(ray, max_distance, self.bounds?, &self.textures.registry)?;
+        Some(lux::RayHit {
+            material: self.textures.sample(hit.block, hit.face, hit.uv()),
+            position: hit.point,
+            normal: Dir3::new_unchecked(hit.face.normal().as_vec3()),
+            distance: hit.distance,
+        })
+    }
+}
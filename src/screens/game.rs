@@ -1,5 +1,5 @@
 use super::ScreenSetup;
-use crate::{AppState, AssetsState, camera_controller::CameraController};
+use crate::{AppState, AssetsState, POINT_LIGHT_RADIUS, camera_controller::CameraController};
 use bevy::{core_pipeline::oit::OrderIndependentTransparencySettings, prelude::*};
 use bevy_asset_loader::prelude::*;
 use std::f32::consts::PI;
@@ -58,6 +58,7 @@ fn setup(mut commands: Commands) {
         PointLight {
             intensity: 1_000_000.0,
             range: 75.0,
+            radius: POINT_LIGHT_RADIUS,
             shadows_enabled: true,
             ..default()
         },
@@ -0,0 +1,329 @@
+//! Compact binary scene format.
+//!
+//! All integers are little endian.
+//!
+//! | Field    | Type                                                             |
+//! |----------|------------------------------------------------------------------|
+//! | magic    | `b"BLOX"`                                                        |
+//! | version  | `u16`                                                            |
+//! | palette  | `u16` count, then per entry `u8` length + name                   |
+//! | chunks   | `u32` count, then per chunk 3 × `i32` chunk coordinate + runs    |
+//! | checksum | `u32` CRC-32 of everything before                                |
+//!
+//! Every chunk holds 16 × 16 × 16 blocks in x, y, z order (x changing fastest), run-length
+//! encoded as runs of varint length + `u8` palette index that add up to exactly the blocks of
+//! the chunk. Only chunks with blocks are stored, so sparse scenes stay small wherever their
+//! blocks are, and a file can't make the reader allocate more chunks than it contains. The
+//! palette maps to blocks by their names in the [`BlockRegistry`], so changing block ids keeps
+//! old files loadable.
+
+use super::{
+    Block, BlockRegistry, BloxScene, SceneFileError,
+    chunk::{self, CHUNK_BLOCK_COUNT, CHUNK_SIZE},
+};
+use bevy::prelude::*;
+
+const MAGIC: &[u8; 4] = b"BLOX";
+const VERSION: u16 = 1;
+
+impl BloxScene {
+    /// Write the scene in the binary format, with the block names of `registry`.
+    pub fn to_bytes(&self, registry: &BlockRegistry) -> Vec<u8> {
+        // Sorted, so the same scene always gives the same file
+        let mut chunks = self.chunks.chunk_coords().collect::<Vec<_>>();
+        chunks.sort_by_key(|chunk| (chunk.z, chunk.y, chunk.x));
+
+        let mut palette = Vec::<Block>::new();
+        let mut chunk_runs = Vec::new();
+        for chunk in chunks {
+            let mut runs = Vec::<(u32, u8)>::new();
+            for block in chunk::positions(chunk).map(|pos| self.block(pos)) {
+                let index = match palette.iter().position(|&b| b == block) {
+                    Some(index) => index,
+                    None => {
+                        palette.push(block);
+                        palette.len() - 1
+                    }
+                } as u8;
+
+                match runs.last_mut() {
+                    Some((length, last)) if *last == index => *length += 1,
+                    _ => runs.push((1, index)),
+                }
+            }
+
+            // Chunks whose blocks were all removed again are left out
+            if !matches!(runs[..], [(_, index)] if palette[index as usize] == Block::AIR) {
+                chunk_runs.push((chunk, runs));
+            }
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+
+        bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+        for block in palette {
+            let name = &registry.definition(block).name;
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+        }
+
+        bytes.extend_from_slice(&(chunk_runs.len() as u32).to_le_bytes());
+        for (chunk, runs) in chunk_runs {
+            for value in chunk.to_array() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            for (length, index) in runs {
+                write_varint(&mut bytes, length);
+                bytes.push(index);
+            }
+        }
+
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+
+        bytes
+    }
+
+    /// Read a scene in the binary format, looking up block names in `registry`.
+    pub fn from_bytes(bytes: &[u8], registry: &BlockRegistry) -> Result<Self, SceneFileError> {
+        // Verify checksum first, so corrupt files are reported as such
+        let Some((content, checksum)) = bytes.split_last_chunk::<4>() else {
+            return Err(SceneFileError::UnexpectedEnd);
+        };
+        if content.len() < MAGIC.len() || &content[..MAGIC.len()] != MAGIC {
+            return Err(SceneFileError::InvalidMagic);
+        }
+        if crc32(content) != u32::from_le_bytes(*checksum) {
+            return Err(SceneFileError::ChecksumMismatch);
+        }
+
+        let mut reader = Reader {
+            bytes: &content[MAGIC.len()..],
+        };
+
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != VERSION {
+            return Err(SceneFileError::UnsupportedVersion(version));
+        }
+
+        let palette = (0..u16::from_le_bytes(reader.array()?))
+            .map(|_| {
+                let length = reader.byte()? as usize;
+                let name = String::from_utf8_lossy(reader.take(length)?);
+                registry
+                    .find(&name)
+                    .ok_or_else(|| SceneFileError::UnknownBlock(name.into()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut scene = BloxScene::empty();
+        for _ in 0..u32::from_le_bytes(reader.array()?) {
+            let mut values = [0; 3];
+            for value in &mut values {
+                *value = i32::from_le_bytes(reader.array()?);
+            }
+            let chunk = IVec3::from_array(values);
+            // The chunk coordinate comes straight from the file
+            if (0..3).any(|axis| chunk[axis].checked_mul(CHUNK_SIZE).is_none()) {
+                return Err(SceneFileError::ChunkOutOfBounds(chunk));
+            }
+
+            let mut positions = chunk::positions(chunk);
+            let mut position = 0;
+            while position < CHUNK_BLOCK_COUNT {
+                let length = reader.varint()? as usize;
+                let index = reader.byte()?;
+                let block = *palette
+                    .get(index as usize)
+                    .ok_or(SceneFileError::InvalidPaletteIndex(index))?;
+
+                if length > CHUNK_BLOCK_COUNT - position {
+                    return Err(SceneFileError::BlockCountMismatch {
+                        expected: CHUNK_BLOCK_COUNT as u64,
+                        found: (position + length) as u64,
+                    });
+                }
+                for pos in positions.by_ref().take(length) {
+                    scene.set_block(pos, block);
+                }
+                position += length;
+            }
+        }
+
+        Ok(scene)
+    }
+}
+
+pub(super) struct Reader<'a> {
+    pub(super) bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn take(&mut self, count: usize) -> Result<&'a [u8], SceneFileError> {
+        let Some((taken, rest)) = self.bytes.split_at_checked(count) else {
+            return Err(SceneFileError::UnexpectedEnd);
+        };
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub(super) fn array<const N: usize>(&mut self) -> Result<[u8; N], SceneFileError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    pub(super) fn byte(&mut self) -> Result<u8, SceneFileError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(super) fn varint(&mut self) -> Result<u32, SceneFileError> {
+        let mut value = 0u32;
+        for shift in (0..32).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SceneFileError::UnexpectedEnd)
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// CRC-32 (IEEE), bitwise.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::default_scene;
+
+    /// Bytes of a file with `content` after the magic, with a valid checksum.
+    fn file(content: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(content);
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn scenes_round_trip() {
+        let registry = BlockRegistry::builtin();
+        let scene = default_scene();
+        let loaded = BloxScene::from_bytes(&scene.to_bytes(&registry), &registry).unwrap();
+        assert_eq!(loaded, scene);
+        assert_eq!(loaded.bounds(), scene.bounds());
+    }
+
+    #[test]
+    fn all_block_ids_round_trip() {
+        let registry = BlockRegistry::with_all_ids();
+        let mut scene = BloxScene::empty();
+        for id in 0..=u8::MAX {
+            scene.set_block(IVec3::new(id as i32 - 100, 1, 0), Block::from_id(id));
+        }
+
+        let loaded = BloxScene::from_bytes(&scene.to_bytes(&registry), &registry).unwrap();
+        assert_eq!(loaded, scene);
+    }
+
+    #[test]
+    fn far_apart_blocks_round_trip() {
+        let registry = BlockRegistry::builtin();
+        let mut scene = BloxScene::empty();
+        scene.set_block(IVec3::splat(-1_000_000_000), Block::STONE);
+        scene.set_block(IVec3::new(100_000, -100_000, 100_000), Block::SAND);
+        scene.set_block(IVec3::splat(1_000_000_000), Block::GLASS);
+
+        // Chunks emptied again are left out
+        scene.set_block(IVec3::new(50, 0, 0), Block::DIRT);
+        scene.set_block(IVec3::new(50, 0, 0), Block::AIR);
+
+        let bytes = scene.to_bytes(&registry);
+        assert!(bytes.len() < 200);
+        assert_eq!(BloxScene::from_bytes(&bytes, &registry).unwrap(), scene);
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected() {
+        let registry = BlockRegistry::builtin();
+        let bytes = default_scene().to_bytes(&registry);
+
+        let mut flipped = bytes.clone();
+        flipped[20] ^= 1;
+        assert!(matches!(
+            BloxScene::from_bytes(&flipped, &registry),
+            Err(SceneFileError::ChecksumMismatch)
+        ));
+
+        let truncated = file(&bytes[MAGIC.len()..bytes.len() - 10]);
+        assert!(matches!(
+            BloxScene::from_bytes(&truncated, &registry),
+            Err(SceneFileError::UnexpectedEnd)
+        ));
+
+        assert!(matches!(
+            BloxScene::from_bytes(&bytes[..3], &registry),
+            Err(SceneFileError::UnexpectedEnd)
+        ));
+    }
+
+    #[test]
+    fn invalid_chunks_are_rejected() {
+        let registry = BlockRegistry::builtin();
+        let chunk_file = |chunk: IVec3, run_length: u32| {
+            let mut content = VERSION.to_le_bytes().to_vec();
+            content.extend_from_slice(&1u16.to_le_bytes());
+            content.push(5);
+            content.extend_from_slice(b"stone");
+            content.extend_from_slice(&1u32.to_le_bytes());
+            for value in chunk.to_array() {
+                content.extend_from_slice(&value.to_le_bytes());
+            }
+            write_varint(&mut content, run_length);
+            content.push(0);
+            file(&content)
+        };
+
+        let full = chunk_file(IVec3::ZERO, CHUNK_BLOCK_COUNT as u32);
+        let scene = BloxScene::from_bytes(&full, &registry).unwrap();
+        assert_eq!(
+            scene.bounds(),
+            Some((IVec3::ZERO, IVec3::splat(CHUNK_SIZE)))
+        );
+
+        let beyond = chunk_file(IVec3::new(i32::MAX, 0, 0), CHUNK_BLOCK_COUNT as u32);
+        assert!(matches!(
+            BloxScene::from_bytes(&beyond, &registry),
+            Err(SceneFileError::ChunkOutOfBounds(_))
+        ));
+
+        let overlong = chunk_file(IVec3::ZERO, u32::MAX);
+        assert!(matches!(
+            BloxScene::from_bytes(&overlong, &registry),
+            Err(SceneFileError::BlockCountMismatch { .. })
+        ));
+    }
+}
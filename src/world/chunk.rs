@@ -0,0 +1,92 @@
+//! Chunked block storage, shared by [`BloxScene`](super::BloxScene) and
+//! [`BloxWorld`](super::BloxWorld).
+
+use bevy::{platform::collections::HashMap, prelude::*};
+
+pub(super) const CHUNK_SIZE: i32 = 16;
+pub(super) const CHUNK_BLOCK_COUNT: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Values stored in `CHUNK_SIZE`³ chunks keyed by chunk coordinate. Chunks are allocated when a
+/// value in them is first written, so positions can be anywhere.
+#[derive(Debug, Clone)]
+pub(super) struct Chunks<T> {
+    chunks: HashMap<IVec3, Box<[T; CHUNK_BLOCK_COUNT]>>,
+}
+
+impl<T> Default for Chunks<T> {
+    fn default() -> Self {
+        Self {
+            chunks: HashMap::default(),
+        }
+    }
+}
+
+impl<T: Copy + Default> Chunks<T> {
+    pub(super) fn get(&self, pos: IVec3) -> Option<&T> {
+        let (chunk, index) = split(pos);
+        self.chunks.get(&chunk).map(|values| &values[index])
+    }
+
+    pub(super) fn get_mut(&mut self, pos: IVec3) -> Option<&mut T> {
+        let (chunk, index) = split(pos);
+        self.chunks.get_mut(&chunk).map(|values| &mut values[index])
+    }
+
+    /// Like [`Chunks::get_mut`], but allocates the chunk filled with default values if needed.
+    pub(super) fn get_or_insert(&mut self, pos: IVec3) -> &mut T {
+        let (chunk, index) = split(pos);
+        let values = self
+            .chunks
+            .entry(chunk)
+            .or_insert_with(|| Box::new([T::default(); CHUNK_BLOCK_COUNT]));
+        &mut values[index]
+    }
+
+    /// Every position of every allocated chunk, in no particular order.
+    pub(super) fn iter(&self) -> impl Iterator<Item = (IVec3, &T)> {
+        self.chunks.iter().flat_map(|(&chunk, values)| {
+            values
+                .iter()
+                .enumerate()
+                .map(move |(index, value)| (join(chunk, index), value))
+        })
+    }
+
+    /// Bytes used by the allocated chunks.
+    pub(super) fn memory_size(&self) -> usize {
+        self.chunks.len() * size_of::<[T; CHUNK_BLOCK_COUNT]>()
+    }
+
+    /// Coordinates of the allocated chunks.
+    pub(super) fn chunk_coords(&self) -> impl Iterator<Item = IVec3> {
+        self.chunks.keys().copied()
+    }
+}
+
+/// Positions of the blocks of `chunk`, with x changing fastest, then y, then z.
+pub(super) fn positions(chunk: IVec3) -> impl Iterator<Item = IVec3> {
+    (0..CHUNK_BLOCK_COUNT).map(move |index| join(chunk, index))
+}
+
+/// Coordinate of the chunk containing `pos`.
+pub(super) fn chunk_coord(pos: IVec3) -> IVec3 {
+    split(pos).0
+}
+
+/// Chunk coordinate and index in the chunk, with x changing fastest, then y, then z.
+fn split(pos: IVec3) -> (IVec3, usize) {
+    let chunk = pos.div_euclid(IVec3::splat(CHUNK_SIZE));
+    let local = pos.rem_euclid(IVec3::splat(CHUNK_SIZE));
+    let index = local.x + local.y * CHUNK_SIZE + local.z * CHUNK_SIZE * CHUNK_SIZE;
+    (chunk, index as usize)
+}
+
+fn join(chunk: IVec3, index: usize) -> IVec3 {
+    let index = index as i32;
+    let local = IVec3::new(
+        index % CHUNK_SIZE,
+        index / CHUNK_SIZE % CHUNK_SIZE,
+        index / (CHUNK_SIZE * CHUNK_SIZE),
+    );
+    chunk * CHUNK_SIZE + local
+}
@@ -0,0 +1,199 @@
+//! Loading and saving scenes, see [`binary`](super::binary), [`text`](super::text),
+//! [`vox`](super::vox) and [`schematic`](super::schematic) for the formats.
+
+use super::{
+    BlockRegistry, BloxScene, SchematicImport, SchematicImportOptions, VoxImport, VoxImportOptions,
+    VoxMapping,
+};
+use bevy::prelude::*;
+use std::{fmt, fs, io, path::Path};
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(io::Error),
+    Parse(String),
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    UnknownBlock(String),
+    UnknownSymbol(char),
+    InvalidPaletteIndex(u8),
+    BlockCountMismatch {
+        expected: u64,
+        found: u64,
+    },
+    ChecksumMismatch,
+    UnexpectedEnd,
+    MissingChunk(&'static str),
+    ModelTooLarge {
+        size: UVec3,
+        max: UVec3,
+    },
+    MissingTag(&'static str),
+    RegionTooLarge {
+        size: UVec3,
+        max: UVec3,
+    },
+    /// The box of a scene file reaches beyond the largest block coordinate.
+    RegionOutOfBounds {
+        min: IVec3,
+        size: UVec3,
+    },
+    ChunkOutOfBounds(IVec3),
+    /// Blocks of the scene the file format can't store.
+    BlocksDropped(usize),
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Parse(err) => write!(f, "{err}"),
+            Self::InvalidMagic => write!(f, "not a blox scene file"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported scene file version {version}")
+            }
+            Self::UnknownBlock(name) => write!(f, "unknown block {name:?}"),
+            Self::UnknownSymbol(symbol) => write!(f, "symbol {symbol:?} is not in the palette"),
+            Self::InvalidPaletteIndex(index) => write!(f, "invalid palette index {index}"),
+            Self::BlockCountMismatch { expected, found } => {
+                write!(f, "scene contains {found} blocks, expected {expected}")
+            }
+            Self::ChecksumMismatch => write!(f, "checksum mismatch, file is corrupt"),
+            Self::UnexpectedEnd => write!(f, "unexpected end of file"),
+            Self::MissingChunk(id) => write!(f, "missing {id} chunk"),
+            Self::ModelTooLarge { size, max } => {
+                write!(f, "model size {size} is larger than the limit {max}")
+            }
+            Self::MissingTag(name) => write!(f, "missing or invalid {name} tag"),
+            Self::RegionTooLarge { size, max } => {
+                write!(f, "region size {size} is larger than the limit {max}")
+            }
+            Self::RegionOutOfBounds { min, size } => {
+                write!(f, "region at {min} with size {size} is out of bounds")
+            }
+            Self::ChunkOutOfBounds(chunk) => write!(f, "chunk {chunk} is out of bounds"),
+            Self::BlocksDropped(count) => write!(f, "{count} blocks can't be stored in the file"),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SceneFileError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl BloxScene {
+    /// Save the scene, creating parent directories as needed. The format is picked by extension:
+    /// `.ron` is the text format, `.vox` is MagicaVoxel with the default [`VoxMapping`] and
+    /// everything else is the binary format. Schematics can't be saved. Blocks are written by
+    /// their names in `registry`. Saving a `.vox` file fails if it can't store every block, use
+    /// [`BloxScene::to_vox`] to write it anyway.
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        registry: &BlockRegistry,
+    ) -> Result<(), SceneFileError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        match Format::from_path(path) {
+            Format::Binary => fs::write(path, self.to_bytes(registry))?,
+            Format::Ron => fs::write(path, self.to_ron(registry))?,
+            Format::Vox => {
+                let export = self.to_vox(&VoxMapping::default());
+                if !export.dropped.is_empty() {
+                    return Err(SceneFileError::BlocksDropped(export.dropped.len()));
+                }
+                fs::write(path, export.bytes)?;
+            }
+            Format::Schematic => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "schematics can only be imported",
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a scene saved by [`BloxScene::save`] or a `.schem` or `.litematic` schematic, picking
+    /// the format by extension. Voxels dropped from `.vox` files and unknown schematic blocks are
+    /// logged, use [`BloxScene::import_vox`] or [`BloxScene::import_schematic`] to handle them.
+    /// Block names are looked up in `registry`.
+    pub fn load(path: impl AsRef<Path>, registry: &BlockRegistry) -> Result<Self, SceneFileError> {
+        let path = path.as_ref();
+        match Format::from_path(path) {
+            Format::Binary => Self::from_bytes(&fs::read(path)?, registry),
+            Format::Ron => Self::from_ron(&fs::read_to_string(path)?, registry),
+            Format::Vox => {
+                let import = Self::import_vox(path, &VoxImportOptions::default())?;
+                if !import.dropped.is_empty() {
+                    log::warn!(
+                        "Dropped {} voxels from {}",
+                        import.dropped.len(),
+                        path.display()
+                    );
+                }
+                Ok(import.scene)
+            }
+            Format::Schematic => {
+                let import = Self::import_schematic(path, &SchematicImportOptions::default())?;
+                if !import.unknown_ids.is_empty() {
+                    log::warn!(
+                        "Replaced unknown blocks {:?} in {}",
+                        import.unknown_ids,
+                        path.display()
+                    );
+                }
+                Ok(import.scene)
+            }
+        }
+    }
+
+    pub fn import_vox(
+        path: impl AsRef<Path>,
+        options: &VoxImportOptions,
+    ) -> Result<VoxImport, SceneFileError> {
+        Self::from_vox(&fs::read(path)?, options)
+    }
+
+    pub fn import_schematic(
+        path: impl AsRef<Path>,
+        options: &SchematicImportOptions,
+    ) -> Result<SchematicImport, SceneFileError> {
+        Self::from_schematic(&fs::read(path)?, options)
+    }
+}
+
+enum Format {
+    Binary,
+    Ron,
+    Vox,
+    Schematic,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Self {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("ron") => Self::Ron,
+            Some("vox") => Self::Vox,
+            Some("schem" | "litematic") => Self::Schematic,
+            _ => Self::Binary,
+        }
+    }
+}
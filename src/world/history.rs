@@ -0,0 +1,354 @@
+//! Undo and redo of world edits.
+//!
+//! Every change made through [`BloxWorld::set_block`] and [`BloxWorld::load_scene`] is recorded
+//! with its old and new value. Edits are grouped into transactions, which are undone and redone
+//! as a whole. The oldest transactions are forgotten once the recorded edits use more memory than
+//! the budget allows.
+
+use super::{Block, BloxScene, BloxWorld, Region};
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+/// Default memory budget of the history, in bytes.
+const DEFAULT_HISTORY_BUDGET: usize = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+pub(super) struct History {
+    /// Oldest transaction first.
+    undo: VecDeque<Transaction>,
+    /// Next transaction to redo last.
+    redo: VecDeque<Transaction>,
+    /// Transaction edits are currently recorded into, and how many transactions are nested.
+    open: Option<(Transaction, u32)>,
+    /// Memory used by the undo and redo transactions.
+    size: usize,
+    budget: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: VecDeque::new(),
+            open: None,
+            size: 0,
+            budget: DEFAULT_HISTORY_BUDGET,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Transaction {
+    edits: Vec<Edit>,
+    size: usize,
+}
+
+#[derive(Debug)]
+enum Edit {
+    Block {
+        pos: IVec3,
+        old: Block,
+        new: Block,
+    },
+    /// Changed blocks of a region operation, as position, old and new block. Replayed with the
+    /// meshes of the whole region marked dirty at once.
+    Region {
+        region: Region,
+        blocks: Vec<(IVec3, Block, Block)>,
+    },
+    Scene {
+        old: Box<BloxScene>,
+        new: Box<BloxScene>,
+    },
+}
+
+impl Edit {
+    /// Rough memory use in bytes.
+    fn size(&self) -> usize {
+        size_of::<Self>()
+            + match self {
+                Edit::Block { .. } => 0,
+                Edit::Region { blocks, .. } => blocks.len() * size_of::<(IVec3, Block, Block)>(),
+                Edit::Scene { old, new } => old.memory_size() + new.memory_size(),
+            }
+    }
+}
+
+impl History {
+    pub(super) fn record_block(&mut self, pos: IVec3, old: Block, new: Block) {
+        self.record(Edit::Block { pos, old, new });
+    }
+
+    pub(super) fn record_region(&mut self, region: Region, blocks: Vec<(IVec3, Block, Block)>) {
+        self.record(Edit::Region { region, blocks });
+    }
+
+    pub(super) fn record_scene(&mut self, old: BloxScene, new: BloxScene) {
+        self.record(Edit::Scene {
+            old: Box::new(old),
+            new: Box::new(new),
+        });
+    }
+
+    fn record(&mut self, edit: Edit) {
+        self.clear_redo();
+
+        match &mut self.open {
+            Some((transaction, _)) => {
+                transaction.size += edit.size();
+                transaction.edits.push(edit);
+            }
+            None => self.push_undo(Transaction {
+                size: edit.size(),
+                edits: vec![edit],
+            }),
+        }
+    }
+
+    fn begin(&mut self) {
+        match &mut self.open {
+            Some((_, depth)) => *depth += 1,
+            None => self.open = Some((Transaction::default(), 1)),
+        }
+    }
+
+    fn end(&mut self) {
+        let Some((transaction, depth)) = &mut self.open else {
+            return;
+        };
+        *depth -= 1;
+        if *depth == 0 {
+            let transaction = std::mem::take(transaction);
+            self.open = None;
+            if !transaction.edits.is_empty() {
+                self.push_undo(transaction);
+            }
+        }
+    }
+
+    fn push_undo(&mut self, transaction: Transaction) {
+        self.size += transaction.size;
+        self.undo.push_back(transaction);
+        self.enforce_budget();
+    }
+
+    fn clear_redo(&mut self) {
+        self.size -= self.redo.iter().map(|t| t.size).sum::<usize>();
+        self.redo.clear();
+    }
+
+    /// Forget the oldest transactions until the history fits into its budget. Once there is
+    /// nothing left to undo, the redo steps farthest from the current state go first, so the
+    /// remaining ones still apply.
+    fn enforce_budget(&mut self) {
+        while self.size > self.budget {
+            let Some(transaction) = self.undo.pop_front().or_else(|| self.redo.pop_front()) else {
+                break;
+            };
+            self.size -= transaction.size;
+        }
+    }
+}
+
+impl BloxWorld {
+    /// Group all edits made by `edit` into a single undo step. Transactions can be nested, the
+    /// outermost one decides the undo step.
+    pub fn transaction<R>(&mut self, edit: impl FnOnce(&mut Self) -> R) -> R {
+        self.history.begin();
+        let result = edit(self);
+        self.history.end();
+        result
+    }
+
+    /// Revert the last transaction. Returns `false` if there is nothing to undo or a transaction
+    /// is open.
+    pub fn undo(&mut self) -> bool {
+        if self.history.open.is_some() {
+            return false;
+        }
+        let Some(transaction) = self.history.undo.pop_back() else {
+            return false;
+        };
+
+        for edit in transaction.edits.iter().rev() {
+            match edit {
+                Edit::Block { pos, old, .. } => self.write_block(*pos, *old),
+                Edit::Region { region, blocks } => {
+                    for &(pos, old, _) in blocks.iter().rev() {
+                        self.store_block(pos, old);
+                    }
+                    self.dirty.push_box(region.min, region.max);
+                }
+                Edit::Scene { old, .. } => self.write_scene(old.as_ref().clone()),
+            }
+        }
+
+        self.history.redo.push_back(transaction);
+        true
+    }
+
+    /// Apply the last undone transaction again. Returns `false` if there is nothing to redo or a
+    /// transaction is open.
+    pub fn redo(&mut self) -> bool {
+        if self.history.open.is_some() {
+            return false;
+        }
+        let Some(transaction) = self.history.redo.pop_back() else {
+            return false;
+        };
+
+        for edit in &transaction.edits {
+            match edit {
+                Edit::Block { pos, new, .. } => self.write_block(*pos, *new),
+                Edit::Region { region, blocks } => {
+                    for &(pos, _, new) in blocks {
+                        self.store_block(pos, new);
+                    }
+                    self.dirty.push_box(region.min, region.max);
+                }
+                Edit::Scene { new, .. } => self.write_scene(new.as_ref().clone()),
+            }
+        }
+
+        self.history.undo.push_back(transaction);
+        true
+    }
+
+    /// Forget all undo and redo steps.
+    pub fn clear_history(&mut self) {
+        let budget = self.history.budget;
+        self.history = History {
+            budget,
+            ..default()
+        };
+    }
+
+    /// Maximum memory in bytes used for undo and redo steps. The oldest steps are forgotten when
+    /// it is exceeded, including a single step that doesn't fit at all.
+    pub fn history_budget(&self) -> usize {
+        self.history.budget
+    }
+
+    pub fn set_history_budget(&mut self, budget: usize) {
+        self.history.budget = budget;
+        self.history.enforce_budget();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::Dirty;
+
+    /// Set the blocks from x = 0 to `count` to stone, one undo step each.
+    fn place_stones(world: &mut BloxWorld, count: i32) {
+        for x in 0..count {
+            world.set_block(IVec3::new(x, 0, 0), Block::STONE);
+        }
+    }
+
+    fn stones(world: &BloxWorld, count: i32) -> Vec<bool> {
+        (0..count)
+            .map(|x| world.block(IVec3::new(x, 0, 0)) == Block::STONE)
+            .collect()
+    }
+
+    #[test]
+    fn undo_increases_the_generation() {
+        let mut world = BloxWorld::empty();
+        place_stones(&mut world, 1);
+
+        let generation = world.generation();
+        world.undo();
+        assert!(world.generation() > generation);
+    }
+
+    #[test]
+    fn undo_and_redo_restore_blocks() {
+        let mut world = BloxWorld::empty();
+        place_stones(&mut world, 3);
+
+        assert!(world.undo());
+        assert!(world.undo());
+        assert_eq!(stones(&world, 3), [true, false, false]);
+
+        assert!(world.redo());
+        assert_eq!(stones(&world, 3), [true, true, false]);
+
+        assert!(world.undo());
+        assert!(world.undo());
+        assert!(!world.undo());
+        assert_eq!(stones(&world, 3), [false, false, false]);
+
+        // A new edit discards the redo steps
+        world.set_block(IVec3::new(5, 0, 0), Block::DIRT);
+        assert!(!world.redo());
+    }
+
+    #[test]
+    fn transactions_are_undone_as_a_whole() {
+        let mut world = BloxWorld::empty();
+        world.transaction(|world| {
+            place_stones(world, 2);
+            world.transaction(|world| world.set_block(IVec3::new(2, 0, 0), Block::STONE));
+        });
+
+        assert!(world.undo());
+        assert_eq!(stones(&world, 3), [false, false, false]);
+        assert!(world.redo());
+        assert_eq!(stones(&world, 3), [true, true, true]);
+    }
+
+    #[test]
+    fn region_edits_mark_the_region_dirty_on_undo_and_redo() {
+        let mut world = BloxWorld::empty();
+        let region = Region::from_corners(IVec3::ZERO, IVec3::splat(3));
+        world.fill_region(region, Block::STONE);
+
+        let mut expected = Dirty::Chunks(default());
+        expected.push_box(region.min, region.max);
+        let dirty_chunks = |dirty: &Dirty| match dirty {
+            Dirty::Chunks(chunks) => chunks.clone(),
+            Dirty::All => panic!("expected dirty chunks"),
+        };
+
+        world.dirty = Dirty::Chunks(default());
+        assert!(world.undo());
+        assert_eq!(world.block(IVec3::ONE), Block::AIR);
+        assert_eq!(dirty_chunks(&world.dirty), dirty_chunks(&expected));
+
+        world.dirty = Dirty::Chunks(default());
+        assert!(world.redo());
+        assert_eq!(world.block(IVec3::ONE), Block::STONE);
+        assert_eq!(dirty_chunks(&world.dirty), dirty_chunks(&expected));
+    }
+
+    #[test]
+    fn budget_forgets_the_oldest_undo_steps() {
+        let mut world = BloxWorld::empty();
+        world.set_history_budget(3 * size_of::<Edit>());
+        place_stones(&mut world, 5);
+
+        assert!(world.undo());
+        assert!(world.undo());
+        assert!(world.undo());
+        assert!(!world.undo());
+        assert_eq!(stones(&world, 5), [true, true, false, false, false]);
+    }
+
+    #[test]
+    fn budget_forgets_the_farthest_redo_steps() {
+        let mut world = BloxWorld::empty();
+        place_stones(&mut world, 4);
+        for _ in 0..4 {
+            assert!(world.undo());
+        }
+
+        // Only the next two redo steps are kept, and they still apply in order
+        world.set_history_budget(2 * size_of::<Edit>());
+        assert!(world.redo());
+        assert!(world.redo());
+        assert!(!world.redo());
+        assert_eq!(stones(&world, 4), [true, true, false, false]);
+    }
+}
@@ -0,0 +1,211 @@
+//! Chunk meshes with greedy meshing.
+//!
+//! Only faces between a block and a neighbor that doesn't hide them are emitted. Neighboring
+//! faces in the same plane with the same texture layer are merged into a single quad, whose UVs
+//! span one unit per block so the texture repeats across it. The texture layer and the block id,
+//! which selects the material in the block shader, are stored in [`Mesh::ATTRIBUTE_UV_1`].
+//! Selected blocks are highlighted by the block shader, so selecting them doesn't change the mesh.
+
+use super::{Block, BlockRegistry, chunk::CHUNK_SIZE};
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+};
+
+/// Height of liquid blocks without the same liquid above.
+pub(super) const LIQUID_SURFACE_HEIGHT: f32 = 0.9;
+
+/// Build the mesh of the chunk at chunk coordinate `chunk`, in chunk local coordinates. `block`
+/// looks up blocks in world coordinates, also outside of the chunk, and `registry` defines them.
+/// Returns `None` if the chunk has no visible faces.
+pub(super) fn chunk_mesh(
+    chunk: IVec3,
+    registry: &BlockRegistry,
+    block: impl Fn(IVec3) -> Block,
+) -> Option<Mesh> {
+    let origin = chunk * CHUNK_SIZE;
+    let size = CHUNK_SIZE as usize;
+
+    let mut quads = QuadBuffers::default();
+    let mut mask = vec![None; size * size];
+    for axis in 0..3 {
+        // The face plane is spanned by the u and v axes
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let local = |layer: i32, u: usize, v: usize| {
+            let mut local = IVec3::ZERO;
+            local[axis] = layer;
+            local[u_axis] = u as i32;
+            local[v_axis] = v as i32;
+            local
+        };
+
+        for sign in [-1, 1] {
+            let mut normal = IVec3::ZERO;
+            normal[axis] = sign;
+
+            for layer in 0..CHUNK_SIZE {
+                // Visible faces of this layer
+                for v in 0..size {
+                    for u in 0..size {
+                        let pos = origin + local(layer, u, v);
+                        mask[v * size + u] = visible_face(pos, normal, registry, &block);
+                    }
+                }
+
+                // Greedily merge them into quads, first along u, then along v
+                for v in 0..size {
+                    for u in 0..size {
+                        let Some(face) = mask[v * size + u] else {
+                            continue;
+                        };
+
+                        let width = (u..size)
+                            .take_while(|&u| mask[v * size + u] == Some(face))
+                            .count();
+                        let height = (v..size)
+                            .take_while(|&v| {
+                                mask[v * size + u..v * size + u + width]
+                                    .iter()
+                                    .all(|&other| other == Some(face))
+                            })
+                            .count();
+
+                        for v in v..v + height {
+                            mask[v * size + u..v * size + u + width].fill(None);
+                        }
+
+                        quads.push(
+                            local(layer, u, v),
+                            normal,
+                            [(u_axis, width), (v_axis, height)],
+                            face,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    quads.into_mesh()
+}
+
+/// What a face looks like, faces are only merged if this is equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Face {
+    texture_layer: u32,
+    block: Block,
+    /// Lowered to [`LIQUID_SURFACE_HEIGHT`].
+    is_liquid_surface: bool,
+}
+
+fn visible_face(
+    pos: IVec3,
+    normal: IVec3,
+    registry: &BlockRegistry,
+    block: &impl Fn(IVec3) -> Block,
+) -> Option<Face> {
+    let this = block(pos);
+    if this == Block::AIR {
+        return None;
+    }
+    let definition = registry.definition(this);
+
+    let is_liquid_surface = !definition.solid && block(pos + IVec3::Y) != this;
+
+    // Liquid surfaces are below the block above, so their top is always visible
+    let neighbor = block(pos + normal);
+    let is_hidden = !registry.definition(neighbor).transparent || neighbor == this;
+    if is_hidden && !(is_liquid_surface && normal == IVec3::Y) {
+        return None;
+    }
+
+    Some(Face {
+        texture_layer: definition.texture_layer(normal) as u32,
+        block: this,
+        is_liquid_surface,
+    })
+}
+
+#[derive(Default)]
+struct QuadBuffers {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    /// Texture layer and block id.
+    uvs_b: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl QuadBuffers {
+    /// Add a quad on the `normal` side of the block at `local`, extending along two axes by a
+    /// number of blocks.
+    fn push(&mut self, local: IVec3, normal: IVec3, extents: [(usize, usize); 2], face: Face) {
+        let mut min = local.as_vec3() + normal.max(IVec3::ZERO).as_vec3();
+        if face.is_liquid_surface && normal == IVec3::Y {
+            min.y -= 1.0 - LIQUID_SURFACE_HEIGHT;
+        }
+
+        // Corners in counter clockwise order around the u and v axes
+        let [(u_axis, width), (v_axis, height)] = extents;
+        let (mut du, mut dv) = (Vec3::ZERO, Vec3::ZERO);
+        du[u_axis] = width as f32;
+        dv[v_axis] = height as f32;
+        let mut corners = [min, min + du, min + du + dv, min + dv];
+
+        // Liquid surfaces are never merged vertically, so lowering their top corners is enough
+        if face.is_liquid_surface && normal.y == 0 {
+            let top = local.y as f32 + 1.0;
+            for corner in corners.iter_mut().filter(|corner| corner.y == top) {
+                corner.y = local.y as f32 + LIQUID_SURFACE_HEIGHT;
+            }
+        }
+
+        // UVs follow block coordinates, with v pointing down on the sides
+        let uv = |corner: Vec3| -> [f32; 2] {
+            let pos = corner.round();
+            match normal.to_array() {
+                [1, 0, 0] => [-pos.z, -pos.y],
+                [-1, 0, 0] => [pos.z, -pos.y],
+                [0, 1, 0] => [pos.x, pos.z],
+                [0, -1, 0] => [pos.x, -pos.z],
+                [0, 0, 1] => [pos.x, -pos.y],
+                _ => [-pos.x, -pos.y],
+            }
+        };
+
+        let start = self.positions.len() as u32;
+        for corner in corners {
+            self.positions.push(corner.to_array());
+            self.normals.push(normal.as_vec3().to_array());
+            self.uvs.push(uv(corner));
+            self.uvs_b
+                .push([face.texture_layer as f32, face.block.id() as f32]);
+        }
+
+        // Flip the winding if the corners go clockwise seen from the normal
+        let indices = match du.cross(dv).dot(normal.as_vec3()) > 0.0 {
+            true => [0, 1, 2, 2, 3, 0],
+            false => [0, 2, 1, 0, 3, 2],
+        };
+        self.indices.extend(indices.map(|i| start + i));
+    }
+
+    fn into_mesh(self) -> Option<Mesh> {
+        if self.indices.is_empty() {
+            return None;
+        }
+
+        Some(
+            Mesh::new(
+                PrimitiveTopology::TriangleList,
+                RenderAssetUsages::RENDER_WORLD,
+            )
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_1, self.uvs_b)
+            .with_inserted_indices(Indices::U32(self.indices)),
+        )
+    }
+}
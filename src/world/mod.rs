@@ -0,0 +1,686 @@
+mod binary;
+mod chunk;
+mod file;
+mod history;
+mod mesh;
+mod raycast;
+mod region;
+mod registry;
+mod schematic;
+mod text;
+mod vox;
+
+pub use self::{
+    file::SceneFileError,
+    raycast::{BlockFace, BlockHit, RaycastHit},
+    region::{Axis, Region},
+    registry::{
+        BlockDefinition, BlockMaterial, BlockRegistry, BlockRegistryChanged, BlockRegistryError,
+        CurrentBlockRegistry,
+    },
+    schematic::{SchematicImport, SchematicImportOptions},
+    vox::{
+        DropReason, DroppedBlock, DroppedVoxel, VoxExport, VoxImport, VoxImportOptions, VoxMapping,
+        VoxOversize,
+    },
+};
+
+use crate::{AppState, AssetsState, key_bindings::KeyBindings, screens::ScreenSetup};
+use bevy::{
+    asset::RenderAssetUsages,
+    image::{ImageAddressMode, ImageSampler, ImageSamplerDescriptor},
+    pbr::{ExtendedMaterial, MaterialExtension},
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+    render::render_resource::{AsBindGroup, Extent3d, ShaderRef, TextureDimension, TextureFormat},
+};
+use bevy_asset_loader::prelude::*;
+use chunk::Chunks;
+use history::History;
+use std::{path::Path, sync::Arc};
+
+/// Number of boxes of [`BloxWorld::selection`] the block shader highlights, the array size in
+/// `block.wgsl`.
+const MAX_SELECTION_BOXES: usize = 4;
+
+/// Default limit for the size of imported models and schematics.
+const DEFAULT_MAX_IMPORT_SIZE: UVec3 = UVec3::splat(256);
+
+pub fn plugin(app: &mut App) {
+    app.add_plugins((
+        registry::plugin,
+        MaterialPlugin::<ExtendedMaterial<StandardMaterial, BlockExtension>>::default(),
+    ));
+
+    // Setup and cleanup
+    app.add_systems(OnEnter(AppState::Game), setup.after(ScreenSetup));
+    app.add_systems(OnExit(AppState::Game), cleanup);
+
+    // Assets
+    app.configure_loading_state(
+        LoadingStateConfig::new(AssetsState::Loading)
+            .load_collection::<WorldAssets>()
+            .finally_init_resource::<WorldAssetsDyn>(),
+    );
+
+    // Update world
+    app.add_systems(
+        Update,
+        reload_block_texture.run_if(on_event::<BlockRegistryChanged>),
+    );
+    app.add_systems(
+        PostUpdate,
+        (
+            update_world.run_if(in_state(AppState::Game)),
+            update_selection.run_if(resource_exists_and_changed::<BloxWorld>),
+        ),
+    );
+
+    // Quick save and load
+    #[cfg(not(target_arch = "wasm32"))]
+    app.add_systems(Update, save_and_load.run_if(in_state(AppState::Game)));
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct WorldAssets {
+    /// Loaded together with the block textures.
+    #[asset(path = "blocks.registry.ron")]
+    pub block_registry: Handle<BlockRegistry>,
+
+    #[expect(unused)] // Only place this here to ensure the shader is loaded
+    #[asset(path = "shaders/block.wgsl")]
+    block_shader: Handle<Shader>,
+}
+
+#[derive(Resource)]
+struct WorldAssetsDyn {
+    block_material: Handle<ExtendedMaterial<StandardMaterial, BlockExtension>>,
+    /// Array texture with the textures of the block registry as layers.
+    block_texture: Handle<Image>,
+}
+
+impl FromWorld for WorldAssetsDyn {
+    fn from_world(world: &mut World) -> Self {
+        // Use the loaded registry from now on
+        let world_assets = world.resource::<WorldAssets>();
+        let registry = world.resource::<Assets<BlockRegistry>>();
+        let registry = Arc::new(registry.get(&world_assets.block_registry).unwrap().clone());
+        if let Err(err) = registry.validate_images(world.resource::<Assets<Image>>()) {
+            panic!("Invalid block textures: {err}");
+        }
+        world.insert_resource(CurrentBlockRegistry(registry.clone()));
+
+        //
+        let image = block_array_texture(&registry, world.resource::<Assets<Image>>());
+        let block_texture = world.resource_mut::<Assets<Image>>().add(image);
+
+        //
+        // TODO: Create two of these, one for opaque/mask and one for blend
+        let mut materials =
+            world.resource_mut::<Assets<ExtendedMaterial<StandardMaterial, BlockExtension>>>();
+        let block_material = materials.add(ExtendedMaterial {
+            base: StandardMaterial {
+                alpha_mode: AlphaMode::Blend,
+                ..default()
+            },
+            extension: BlockExtension {
+                blocks: block_texture.clone(),
+                materials: block_materials_uniform(&registry),
+                selection: selection_uniform(&[]),
+            },
+        });
+
+        Self {
+            block_material,
+            block_texture,
+        }
+    }
+}
+
+/// Array texture of the textures of `registry`, in texture layer order. The images must have
+/// passed [`BlockRegistry::validate_images`].
+fn block_array_texture(registry: &BlockRegistry, images: &Assets<Image>) -> Image {
+    let mut array_texture = Vec::new();
+    let (mut size, mut layers) = (0, 0);
+    for handle in registry.images() {
+        let image = images.get(handle).unwrap();
+        array_texture.extend_from_slice(image.data.as_ref().unwrap());
+        size = image.width();
+        layers += 1;
+    }
+
+    let mut image = Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: layers,
+        },
+        TextureDimension::D2,
+        array_texture,
+        TextureFormat::bevy_default(),
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    // Merged faces span multiple blocks, so the texture has to repeat
+    image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::Repeat,
+        address_mode_v: ImageAddressMode::Repeat,
+        ..ImageSamplerDescriptor::linear()
+    });
+    image
+}
+
+/// Rebuild the array texture and the chunk meshes for the changed block registry.
+fn reload_block_texture(
+    world_assets: Res<WorldAssetsDyn>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, BlockExtension>>>,
+    world: Option<ResMut<BloxWorld>>,
+    registry: Res<CurrentBlockRegistry>,
+) {
+    let image = block_array_texture(&registry, &images);
+    if let Some(block_texture) = images.get_mut(&world_assets.block_texture) {
+        *block_texture = image;
+    }
+    // Let the material pick up the new texture
+    if let Some(material) = materials.get_mut(&world_assets.block_material) {
+        material.extension.materials = block_materials_uniform(&registry);
+    }
+
+    // Texture layers and transparency can change too
+    if let Some(mut world) = world {
+        world.dirty = Dirty::All;
+    }
+}
+
+fn setup(mut commands: Commands) {
+    commands.insert_resource(BloxWorld::from_scene(&default_scene()));
+}
+
+fn cleanup(mut commands: Commands) {
+    commands.remove_resource::<BloxWorld>();
+}
+
+fn update_world(
+    mut commands: Commands,
+    mut world: ResMut<BloxWorld>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    world_assets: Res<WorldAssetsDyn>,
+    registry: Res<CurrentBlockRegistry>,
+) {
+    // Only mark the world as changed when there is something to update
+    if world.is_dirty() {
+        world.update(&mut commands, &mut meshes, &world_assets, &registry);
+    }
+}
+
+/// Pass the selection of the world to the block shader.
+fn update_selection(
+    world: Res<BloxWorld>,
+    world_assets: Res<WorldAssetsDyn>,
+    mut materials: ResMut<Assets<ExtendedMaterial<StandardMaterial, BlockExtension>>>,
+) {
+    let selection = selection_uniform(&world.selection);
+    let is_changed = materials
+        .get(&world_assets.block_material)
+        .is_some_and(|material| material.extension.selection != selection);
+    if is_changed && let Some(material) = materials.get_mut(&world_assets.block_material) {
+        material.extension.selection = selection;
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_and_load(
+    mut world: ResMut<BloxWorld>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    registry: Res<CurrentBlockRegistry>,
+) {
+    const PATH: &str = "saves/world.blox";
+
+    if key_bindings.quick_save.just_pressed(&keyboard_input) {
+        match world.save(PATH, &registry) {
+            Ok(()) => log::info!("Saved world to {PATH}"),
+            Err(err) => log::error!("Failed to save world to {PATH}: {err}"),
+        }
+    } else if key_bindings.quick_load.just_pressed(&keyboard_input) {
+        match world.load(PATH, &registry) {
+            Ok(()) => log::info!("Loaded world from {PATH}"),
+            Err(err) => log::error!("Failed to load world from {PATH}: {err}"),
+        }
+    }
+}
+
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+struct BlockExtension {
+    #[texture(100, dimension = "2d_array")]
+    #[sampler(101)]
+    blocks: Handle<Image>,
+    /// Roughness, metallic and reflectance of the [`BlockMaterial`] of every block id, overriding
+    /// those of the [`StandardMaterial`].
+    #[uniform(103)]
+    materials: [Vec4; 256],
+    /// Boxes of [`BloxWorld::selection`] as inclusive min and exclusive max corner, unused boxes
+    /// are empty.
+    #[uniform(102)]
+    selection: [IVec4; 2 * MAX_SELECTION_BOXES],
+}
+
+/// Materials for [`BlockExtension::materials`], indexed by block id.
+fn block_materials_uniform(registry: &BlockRegistry) -> [Vec4; 256] {
+    let mut uniform = [Vec4::ZERO; 256];
+    for (id, properties) in uniform.iter_mut().enumerate() {
+        let material = &registry.definition(Block::from_id(id as u8)).material;
+        *properties = Vec4::new(
+            material.roughness,
+            material.metallic,
+            material.reflectance,
+            0.0,
+        );
+    }
+    uniform
+}
+
+/// Boxes for [`BlockExtension::selection`], only the first [`MAX_SELECTION_BOXES`] regions are
+/// highlighted.
+fn selection_uniform(selection: &[Region]) -> [IVec4; 2 * MAX_SELECTION_BOXES] {
+    let mut uniform = [IVec4::ZERO; 2 * MAX_SELECTION_BOXES];
+    for (corners, region) in uniform.chunks_mut(2).zip(selection) {
+        corners[0] = region.min.extend(0);
+        corners[1] = region.max.extend(0);
+    }
+    uniform
+}
+
+impl MaterialExtension for BlockExtension {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/block.wgsl".into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        "shaders/block.wgsl".into()
+    }
+}
+
+/// Blocks without rendering state. Unbounded, blocks can be placed at any position.
+#[derive(Debug, Clone, Default)]
+pub struct BloxScene {
+    chunks: Chunks<Block>,
+}
+
+impl BloxScene {
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// Block at `pos`, air where nothing was placed.
+    pub fn block(&self, pos: IVec3) -> Block {
+        self.chunks.get(pos).copied().unwrap_or_default()
+    }
+
+    pub fn set_block(&mut self, pos: IVec3, block: Block) {
+        match block {
+            Block::AIR => {
+                if let Some(old) = self.chunks.get_mut(pos) {
+                    *old = block;
+                }
+            }
+            _ => *self.chunks.get_or_insert(pos) = block,
+        }
+    }
+
+    /// Positions and blocks other than air, in no particular order.
+    pub fn blocks(&self) -> impl Iterator<Item = (IVec3, Block)> {
+        self.chunks
+            .iter()
+            .filter(|&(_, &block)| block != Block::AIR)
+            .map(|(pos, &block)| (pos, block))
+    }
+
+    /// Smallest box containing all blocks other than air, as inclusive min and exclusive max
+    /// corner. `None` if the scene is empty.
+    pub fn bounds(&self) -> Option<(IVec3, IVec3)> {
+        self.blocks().fold(None, |bounds, (pos, _)| match bounds {
+            Some((min, max)) => Some((pos.min(min), (pos + 1).max(max))),
+            None => Some((pos, pos + 1)),
+        })
+    }
+
+    /// Bytes used to store the blocks.
+    pub(super) fn memory_size(&self) -> usize {
+        self.chunks.memory_size()
+    }
+}
+
+impl PartialEq for BloxScene {
+    fn eq(&self, other: &Self) -> bool {
+        self.blocks().all(|(pos, block)| other.block(pos) == block)
+            && other.blocks().all(|(pos, block)| self.block(pos) == block)
+    }
+}
+
+/// The blocks of the game, rendered with one mesh per chunk.
+#[derive(Debug, Resource)]
+pub struct BloxWorld {
+    scene: BloxScene,
+    /// Kept up to date when placing blocks, recomputed on update when removing blocks shrinks it.
+    bounds: Option<(IVec3, IVec3)>,
+    bounds_outdated: bool,
+    selection: Vec<Region>,
+    history: History,
+    chunk_entities: HashMap<IVec3, Entity>,
+    dirty: Dirty,
+    generation: u64,
+}
+
+impl BloxWorld {
+    pub fn empty() -> Self {
+        Self {
+            scene: BloxScene::empty(),
+            bounds: None,
+            bounds_outdated: false,
+            selection: Vec::new(),
+            history: History::default(),
+            chunk_entities: HashMap::default(),
+            dirty: Dirty::Chunks(HashSet::default()),
+            generation: 0,
+        }
+    }
+
+    /// A world with the blocks of `scene` and no history.
+    pub fn from_scene(scene: &BloxScene) -> Self {
+        let mut world = Self::empty();
+        world.write_scene(scene.clone());
+        world
+    }
+
+    pub fn to_scene(&self) -> BloxScene {
+        self.scene.clone()
+    }
+
+    /// Block at `pos`, air where nothing was placed.
+    pub fn block(&self, pos: IVec3) -> Block {
+        self.scene.block(pos)
+    }
+
+    /// Change a block, recorded in the history.
+    pub fn set_block(&mut self, pos: IVec3, block: Block) {
+        let old = self.scene.block(pos);
+        if old == block {
+            return;
+        }
+        self.history.record_block(pos, old, block);
+        self.write_block(pos, block);
+    }
+
+    /// Replace all blocks with the blocks of `scene`, recorded in the history.
+    pub fn load_scene(&mut self, scene: &BloxScene) {
+        self.history.record_scene(self.scene.clone(), scene.clone());
+        self.write_scene(scene.clone());
+    }
+
+    /// Change a block without recording it, keeping bounds and meshes up to date.
+    fn write_block(&mut self, pos: IVec3, block: Block) {
+        self.store_block(pos, block);
+        self.dirty.push_block(pos);
+    }
+
+    /// Like [`BloxWorld::write_block`], but leaves marking meshes dirty to the caller.
+    fn store_block(&mut self, pos: IVec3, block: Block) {
+        self.scene.set_block(pos, block);
+        self.generation += 1;
+
+        match (block, self.bounds) {
+            (Block::AIR, Some((min, max))) => {
+                self.bounds_outdated |= pos.cmpeq(min).any() || (pos + 1).cmpeq(max).any();
+            }
+            (Block::AIR, None) => (),
+            (_, Some((min, max))) => self.bounds = Some((pos.min(min), (pos + 1).max(max))),
+            (_, None) => self.bounds = Some((pos, pos + 1)),
+        }
+    }
+
+    fn write_scene(&mut self, scene: BloxScene) {
+        self.bounds = scene.bounds();
+        self.scene = scene;
+        self.bounds_outdated = false;
+        self.dirty = Dirty::All;
+        self.generation += 1;
+    }
+
+    /// Counter increased by every change of the blocks, to notice changes without comparing
+    /// scenes. Unlike the change ticks of the resource, mesh updates don't count.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// See [`BloxScene::bounds`]. After removing blocks this can be too large until the world is
+    /// updated.
+    pub fn bounds(&self) -> Option<(IVec3, IVec3)> {
+        self.bounds
+    }
+
+    /// Boxes of blocks highlighted in the game, like the one under the cursor. Highlighting is
+    /// done by the block shader, changing it doesn't rebuild any chunk meshes.
+    pub fn selection(&self) -> &[Region] {
+        &self.selection
+    }
+
+    pub fn set_selection(&mut self, selection: Vec<Region>) {
+        self.selection = selection;
+    }
+
+    /// Height of the ground plane, the bottom of the blocks or 0 if there are none.
+    pub fn ground_height(&self) -> i32 {
+        self.bounds.map_or(0, |(min, _)| min.y)
+    }
+
+    /// Save the blocks of the world, see [`BloxScene::save`].
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        registry: &BlockRegistry,
+    ) -> Result<(), SceneFileError> {
+        self.to_scene().save(path, registry)
+    }
+
+    /// Replace the blocks of the world with a scene saved by [`BloxWorld::save`]. The world is
+    /// left unchanged if loading fails.
+    pub fn load(
+        &mut self,
+        path: impl AsRef<Path>,
+        registry: &BlockRegistry,
+    ) -> Result<(), SceneFileError> {
+        let scene = BloxScene::load(path, registry)?;
+        self.load_scene(&scene);
+        Ok(())
+    }
+
+    fn is_dirty(&self) -> bool {
+        match &self.dirty {
+            Dirty::Chunks(chunks) => !chunks.is_empty(),
+            Dirty::All => true,
+        }
+    }
+
+    fn update(
+        &mut self,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        world_assets: &WorldAssetsDyn,
+        registry: &BlockRegistry,
+    ) {
+        if self.bounds_outdated {
+            self.bounds = self.scene.bounds();
+            self.bounds_outdated = false;
+        }
+
+        let chunks = match std::mem::replace(&mut self.dirty, Dirty::Chunks(HashSet::default())) {
+            Dirty::Chunks(chunks) => chunks,
+            Dirty::All => self
+                .scene
+                .chunks
+                .chunk_coords()
+                .chain(self.chunk_entities.keys().copied())
+                .collect(),
+        };
+
+        for chunk in chunks {
+            let mesh = mesh::chunk_mesh(chunk, registry, |pos| self.scene.block(pos));
+            match (mesh, self.chunk_entities.get(&chunk)) {
+                (Some(mesh), Some(&entity)) => {
+                    commands.entity(entity).insert(Mesh3d(meshes.add(mesh)));
+                }
+                (Some(mesh), None) => {
+                    let entity = commands
+                        .spawn((
+                            Name::new("Chunk"),
+                            Transform::from_translation((chunk * chunk::CHUNK_SIZE).as_vec3()),
+                            Mesh3d(meshes.add(mesh)),
+                            MeshMaterial3d(world_assets.block_material.clone()),
+                            StateScoped(AppState::Game),
+                        ))
+                        .id();
+                    self.chunk_entities.insert(chunk, entity);
+                }
+                (None, Some(_)) => {
+                    let entity = self.chunk_entities.remove(&chunk).unwrap();
+                    commands.entity(entity).despawn();
+                }
+                (None, None) => (),
+            }
+        }
+    }
+}
+
+/// Chunks whose meshes need to be rebuilt.
+#[derive(Debug)]
+enum Dirty {
+    Chunks(HashSet<IVec3>),
+    All,
+}
+
+impl Dirty {
+    fn push(&mut self, chunk: IVec3) {
+        match self {
+            Dirty::Chunks(chunks) => {
+                chunks.insert(chunk);
+            }
+            Dirty::All => (),
+        }
+    }
+
+    /// Mark the chunk of a changed block. Faces of the neighbors and the liquid surface below
+    /// depend on the block too, so neighboring chunks are marked if the block is on their border.
+    fn push_block(&mut self, pos: IVec3) {
+        let chunk = chunk::chunk_coord(pos);
+        self.push(chunk);
+        for offset in [
+            IVec3::new(-1, 0, 0),
+            IVec3::new(1, 0, 0),
+            IVec3::new(0, -1, 0),
+            IVec3::new(0, 1, 0),
+            IVec3::new(0, 0, -1),
+            IVec3::new(0, 0, 1),
+        ] {
+            let neighbor = chunk::chunk_coord(pos + offset);
+            if neighbor != chunk {
+                self.push(neighbor);
+            }
+        }
+    }
+
+    /// Mark all chunks touching the blocks from `min` to `max` exclusive, or their neighbors.
+    fn push_box(&mut self, min: IVec3, max: IVec3) {
+        let (min, max) = (chunk::chunk_coord(min - 1), chunk::chunk_coord(max));
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    self.push(IVec3::new(x, y, z));
+                }
+            }
+        }
+    }
+}
+
+/// Kind of block, an id into the [`BlockRegistry`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Block(u8);
+
+impl Block {
+    pub const AIR: Block = Block(0);
+
+    // Blocks of the built-in registry, used by the default scene and import mappings
+    pub const DIRT: Block = Block(1);
+    pub const STONE: Block = Block(2);
+    pub const SAND: Block = Block(3);
+    pub const GRASS: Block = Block(4);
+    pub const WOOD: Block = Block(5);
+    pub const LEAVES: Block = Block(6);
+    pub const WATER: Block = Block(7);
+    pub const GLASS: Block = Block(8);
+    pub const LAMP: Block = Block(9);
+
+    pub const fn from_id(id: u8) -> Self {
+        Self(id)
+    }
+
+    pub const fn id(&self) -> u8 {
+        self.0
+    }
+}
+
+pub fn default_scene() -> BloxScene {
+    let mut scene = BloxScene::empty();
+
+    let size = 15;
+
+    for x in 0..size {
+        for z in 0..size {
+            scene.set_block(IVec3::new(x, 0, z), Block::STONE);
+
+            scene.set_block(
+                IVec3::new(x, 1, z),
+                if (6..=8).contains(&x) && (6..=8).contains(&z) {
+                    Block::WATER
+                } else {
+                    Block::GRASS
+                },
+            );
+
+            if x == 0 || x == size - 1 || z == 0 || z == size - 1 {
+                scene.set_block(IVec3::new(x, 2, z), Block::WOOD);
+            }
+        }
+    }
+
+    scene.set_block(IVec3::new(9, 2, 5), Block::SAND);
+    scene.set_block(IVec3::new(9, 3, 5), Block::SAND);
+
+    for z in 7..=9 {
+        scene.set_block(IVec3::new(4, 2, z), Block::GLASS);
+        scene.set_block(IVec3::new(4, 3, z), Block::GLASS);
+    }
+
+    scene
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_changes_increase_the_generation() {
+        let mut world = BloxWorld::empty();
+        let generation = world.generation();
+        world.set_block(IVec3::ZERO, Block::STONE);
+        assert!(world.generation() > generation);
+
+        let generation = world.generation();
+        world.set_selection(Vec::new());
+        world.set_block(IVec3::ZERO, Block::STONE);
+        assert_eq!(world.generation(), generation);
+
+        world.load_scene(&default_scene());
+        assert!(world.generation() > generation);
+    }
+}
@@ -0,0 +1,433 @@
+//! Voxel ray traversal, shared by [`BloxWorld::raycast`] and the ray tracer.
+
+use super::{Block, BlockRegistry, BloxScene, BloxWorld, mesh::LIQUID_SURFACE_HEIGHT};
+use bevy::prelude::*;
+
+/// Side of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockFace {
+    XNeg,
+    XPos,
+    YNeg,
+    YPos,
+    ZNeg,
+    ZPos,
+}
+
+impl BlockFace {
+    /// Offset from the block to the neighbor on this side.
+    pub fn normal(&self) -> IVec3 {
+        match self {
+            BlockFace::XNeg => IVec3::NEG_X,
+            BlockFace::XPos => IVec3::X,
+            BlockFace::YNeg => IVec3::NEG_Y,
+            BlockFace::YPos => IVec3::Y,
+            BlockFace::ZNeg => IVec3::NEG_Z,
+            BlockFace::ZPos => IVec3::Z,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockHit {
+    /// Position of the block that was hit.
+    pub position: IVec3,
+    pub block: Block,
+    /// Face the ray entered the block through, the top of liquid surfaces.
+    pub face: BlockFace,
+    /// Hit point in world coordinates.
+    pub point: Vec3,
+    /// Distance along the ray to the hit point.
+    pub distance: f32,
+}
+
+impl BlockHit {
+    /// Texture coordinates of the hit point on [`BlockHit::face`], with v pointing down on the
+    /// sides.
+    pub fn uv(&self) -> Vec2 {
+        let rel = self.point - self.position.as_vec3();
+        match self.face {
+            BlockFace::XNeg => Vec2::new(rel.z, 1.0 - rel.y),
+            BlockFace::XPos => Vec2::new(1.0 - rel.z, 1.0 - rel.y),
+            BlockFace::YNeg => Vec2::new(rel.x, 1.0 - rel.z),
+            BlockFace::YPos => Vec2::new(rel.x, rel.z),
+            BlockFace::ZNeg => Vec2::new(1.0 - rel.x, 1.0 - rel.y),
+            BlockFace::ZPos => Vec2::new(rel.x, 1.0 - rel.y),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RaycastHit {
+    Block(BlockHit),
+    /// The ground plane at [`BloxWorld::ground_height`], which extends beyond the blocks.
+    Ground {
+        point: Vec3,
+        distance: f32,
+    },
+}
+
+impl RaycastHit {
+    pub fn point(&self) -> Vec3 {
+        match self {
+            RaycastHit::Block(hit) => hit.point,
+            RaycastHit::Ground { point, .. } => *point,
+        }
+    }
+
+    pub fn distance(&self) -> f32 {
+        match self {
+            RaycastHit::Block(hit) => hit.distance,
+            RaycastHit::Ground { distance, .. } => *distance,
+        }
+    }
+}
+
+impl BloxWorld {
+    /// First block or ground hit by `ray` within `max_distance`, with blocks defined by
+    /// `registry`. A ray starting inside a transparent block passes through blocks of that kind
+    /// until it leaves them.
+    pub fn raycast(
+        &self,
+        ray: Ray3d,
+        max_distance: f32,
+        registry: &BlockRegistry,
+    ) -> Option<RaycastHit> {
+        if let Some(bounds) = self.bounds()
+            && let Some(hit) = self.scene.cast_ray(ray, max_distance, bounds, registry)
+        {
+            return Some(RaycastHit::Block(hit));
+        }
+
+        // Only hit the ground from above
+        let height = self.ground_height() as f32;
+        let distance = (height - ray.origin.y) / ray.direction.y;
+        (ray.origin.y > height && ray.direction.y < 0.0 && distance <= max_distance).then(|| {
+            RaycastHit::Ground {
+                point: ray.get_point(distance),
+                distance,
+            }
+        })
+    }
+}
+
+impl BloxScene {
+    /// First block hit by `ray` within `max_distance`, only looking at blocks inside `bounds`.
+    /// Blocks are defined by `registry`.
+    pub(crate) fn cast_ray(
+        &self,
+        ray: Ray3d,
+        max_distance: f32,
+        (min, max): (IVec3, IVec3),
+        registry: &BlockRegistry,
+    ) -> Option<BlockHit> {
+        fn interval(start: f32, speed: f32, min: f32, max: f32) -> Option<(f32, f32)> {
+            if (start < min && speed <= 0.0) || (start > max && speed >= 0.0) {
+                None
+            } else if speed == 0.0 {
+                (start >= min && start < max).then_some((f32::NEG_INFINITY, f32::INFINITY))
+            } else {
+                let t1 = (min - start) / speed;
+                let t2 = (max - start) / speed;
+                let (t1, t2) = if t1 < t2 { (t1, t2) } else { (t2, t1) };
+                Some((t1.max(0.0), t2))
+            }
+        }
+
+        fn clamp_origin(ray: Ray3d, min: Vec3, max: Vec3) -> Option<Vec3> {
+            if ray.origin.cmpge(min).all() && ray.origin.cmplt(max).all() {
+                return Some(ray.origin);
+            }
+
+            let x = interval(ray.origin.x, ray.direction.x, min.x, max.x)?;
+            let y = interval(ray.origin.y, ray.direction.y, min.y, max.y)?;
+            let z = interval(ray.origin.z, ray.direction.z, min.z, max.z)?;
+
+            let interval = (x.0.max(y.0).max(z.0), x.1.min(y.1).min(z.1));
+
+            (interval.0 <= interval.1).then(|| ray.origin + interval.0 * ray.direction)
+        }
+
+        fn time_to_edge(pos: f32, block: i32, speed: f32) -> (f32, i32) {
+            if speed > 0.0 {
+                (((block as f32) + 1.0 - pos) / speed, 1)
+            } else if speed < 0.0 {
+                (((block as f32) - pos) / speed, -1)
+            } else {
+                (f32::INFINITY, 0)
+            }
+        }
+
+        fn face(pos: Vec3, block: IVec3) -> BlockFace {
+            let rel = pos - block.as_vec3();
+            let (face, _dis) = [
+                (BlockFace::XNeg, f32::abs(rel.x)),
+                (BlockFace::XPos, f32::abs(1.0 - rel.x)),
+                (BlockFace::YNeg, f32::abs(rel.y)),
+                (BlockFace::YPos, f32::abs(1.0 - rel.y)),
+                (BlockFace::ZNeg, f32::abs(rel.z)),
+                (BlockFace::ZPos, f32::abs(1.0 - rel.z)),
+            ]
+            .into_iter()
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .unwrap();
+            face
+        }
+
+        // Clamp origin to scene bounds
+        let mut current_position = clamp_origin(ray, min.as_vec3(), max.as_vec3())?;
+
+        // Current block from position
+        // - floor to get block coordinates
+        // - clamp to scene bounds
+        let mut current_block = current_position.floor().as_ivec3().clamp(min, max - 1);
+
+        // Distance traveled
+        let mut distance = Vec3::distance(ray.origin, current_position);
+
+        // Start block, only ignored if the ray actually starts inside of it
+        let block_start = self.block(current_block);
+        let mut ignore =
+            current_position == ray.origin && registry.definition(block_start).transparent;
+
+        while distance <= max_distance {
+            // Stop if outside of extended scene bounds
+            if current_block.cmplt(min - 1).any() || current_block.cmpge(max).any() {
+                return None;
+            }
+
+            // Check block
+            let block = self.block(current_block);
+            if block != Block::AIR {
+                ignore &= block == block_start;
+                if !ignore {
+                    let mut hit = Some(BlockHit {
+                        position: current_block,
+                        block,
+                        face: face(current_position, current_block),
+                        point: current_position,
+                        distance,
+                    });
+
+                    // Special case top liquid blocks
+                    let rel_y = current_position.y - current_block.y as f32;
+                    if !registry.definition(block).solid
+                        && self.block(current_block + IVec3::Y) != block
+                        && rel_y > LIQUID_SURFACE_HEIGHT
+                    {
+                        hit = None;
+                        if ray.direction.y < 0.0 {
+                            // Try to hit the top face at the liquid surface height
+                            let t = (LIQUID_SURFACE_HEIGHT - rel_y) / ray.direction.y;
+                            let point = current_position + t * ray.direction;
+                            if point.floor().as_ivec3() == current_block {
+                                hit = Some(BlockHit {
+                                    position: current_block,
+                                    block,
+                                    face: BlockFace::YPos,
+                                    point,
+                                    distance: distance + t,
+                                });
+                            }
+                        }
+                    }
+
+                    if hit.is_some() {
+                        return hit;
+                    }
+                }
+            } else {
+                ignore = false;
+            }
+
+            // Find next edge over all 3 axes
+            let (time, delta) = [0, 1, 2]
+                .into_iter()
+                .map(|i| {
+                    let (time, delta_scalar) = time_to_edge(
+                        current_position.to_array()[i],
+                        current_block.to_array()[i],
+                        ray.direction.to_array()[i],
+                    );
+
+                    let mut delta = IVec3::ZERO;
+                    delta[i] = delta_scalar;
+
+                    (time, delta)
+                })
+                .min_by(|(a_time, _), (b_time, _)| a_time.partial_cmp(b_time).unwrap())
+                .unwrap();
+
+            // Step
+            current_position += ray.direction * time;
+            distance += time;
+            current_block += delta;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(blocks: &[(IVec3, Block)]) -> BloxWorld {
+        let mut scene = BloxScene::empty();
+        for &(pos, block) in blocks {
+            scene.set_block(pos, block);
+        }
+        BloxWorld::from_scene(&scene)
+    }
+
+    fn raycast(world: &BloxWorld, ray: Ray3d, max_distance: f32) -> Option<RaycastHit> {
+        world.raycast(ray, max_distance, &BlockRegistry::builtin())
+    }
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray3d {
+        Ray3d::new(origin, Dir3::new(direction).unwrap())
+    }
+
+    fn block_hit(hit: Option<RaycastHit>) -> BlockHit {
+        match hit {
+            Some(RaycastHit::Block(hit)) => hit,
+            hit => panic!("expected a block hit, got {hit:?}"),
+        }
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_the_facing_side() {
+        let world = world(&[(IVec3::new(2, 3, 4), Block::STONE)]);
+        let center = Vec3::new(2.5, 3.5, 4.5);
+
+        for face in [
+            BlockFace::XNeg,
+            BlockFace::XPos,
+            BlockFace::YNeg,
+            BlockFace::YPos,
+            BlockFace::ZNeg,
+            BlockFace::ZPos,
+        ] {
+            let normal = face.normal().as_vec3();
+            let hit = block_hit(raycast(&world, ray(center + 10.0 * normal, -normal), 100.0));
+            assert_eq!(hit.position, IVec3::new(2, 3, 4));
+            assert_eq!(hit.block, Block::STONE);
+            assert_eq!(hit.face, face);
+            assert!(hit.point.distance(center + 0.5 * normal) < 1e-5);
+            assert!((hit.distance - 9.5).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn nearest_block_is_hit_first() {
+        let world = world(&[
+            (IVec3::new(0, 0, 0), Block::STONE),
+            (IVec3::new(3, 0, 0), Block::DIRT),
+        ]);
+
+        let hit = block_hit(raycast(
+            &world,
+            ray(Vec3::new(-2.0, 0.5, 0.5), Vec3::X),
+            100.0,
+        ));
+        assert_eq!(hit.position, IVec3::new(0, 0, 0));
+        assert_eq!(hit.face, BlockFace::XNeg);
+
+        let hit = block_hit(raycast(
+            &world,
+            ray(Vec3::new(6.0, 0.5, 0.5), -Vec3::X),
+            100.0,
+        ));
+        assert_eq!(hit.position, IVec3::new(3, 0, 0));
+        assert_eq!(hit.face, BlockFace::XPos);
+    }
+
+    #[test]
+    fn grazing_rays() {
+        let world = world(&[
+            (IVec3::new(0, 0, 0), Block::STONE),
+            (IVec3::new(1, 0, 0), Block::STONE),
+            (IVec3::new(2, 0, 0), Block::STONE),
+        ]);
+
+        // Nearly parallel to the top, entering through it far from the origin
+        let direction = Vec3::new(1.0, -0.001, 0.0);
+        let hit = block_hit(raycast(
+            &world,
+            ray(Vec3::new(-1.0, 1.0035, 0.5), direction),
+            100.0,
+        ));
+        assert_eq!(hit.position, IVec3::new(2, 0, 0));
+        assert_eq!(hit.face, BlockFace::YPos);
+        assert!((hit.point.y - 1.0).abs() < 1e-5);
+
+        // Parallel just above the top misses the blocks
+        let hit = raycast(&world, ray(Vec3::new(-1.0, 1.001, 0.5), Vec3::X), 100.0);
+        assert_eq!(hit, None);
+
+        // Clipping the far edge of the top
+        let direction = Vec3::new(1.0, -0.1, 0.0);
+        let hit = block_hit(raycast(
+            &world,
+            ray(Vec3::new(-1.0, 1.39, 0.5), direction),
+            100.0,
+        ));
+        assert_eq!(hit.position, IVec3::new(2, 0, 0));
+        assert_eq!(hit.face, BlockFace::YPos);
+        assert!(hit.point.distance(Vec3::new(2.9, 1.0, 0.5)) < 1e-4);
+
+        // Just missing it and hitting the ground instead
+        let hit = raycast(&world, ray(Vec3::new(-1.0, 1.41, 0.5), direction), 100.0);
+        assert!(matches!(hit, Some(RaycastHit::Ground { .. })));
+        assert!(hit.unwrap().point().distance(Vec3::new(13.1, 0.0, 0.5)) < 1e-4);
+    }
+
+    #[test]
+    fn max_distance_limits_hits() {
+        let world = world(&[(IVec3::new(5, 0, 0), Block::STONE)]);
+        let ray = ray(Vec3::new(0.0, 0.5, 0.5), Vec3::X);
+
+        assert!(raycast(&world, ray, 5.5).is_some());
+        assert_eq!(raycast(&world, ray, 4.5), None);
+    }
+
+    #[test]
+    fn water_surface_is_below_the_block_top() {
+        let world = world(&[(IVec3::new(0, 0, 0), Block::WATER)]);
+
+        let hit = block_hit(raycast(
+            &world,
+            ray(Vec3::new(0.5, 3.0, 0.5), -Vec3::Y),
+            100.0,
+        ));
+        assert_eq!(hit.face, BlockFace::YPos);
+        assert!((hit.point.y - LIQUID_SURFACE_HEIGHT).abs() < 1e-5);
+        assert!((hit.distance - (3.0 - LIQUID_SURFACE_HEIGHT)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn ground_is_hit_outside_of_the_blocks() {
+        let world = world(&[(IVec3::new(0, 2, 0), Block::STONE)]);
+
+        let hit = raycast(&world, ray(Vec3::new(10.5, 5.0, 0.5), -Vec3::Y), 100.0);
+        assert_eq!(
+            hit,
+            Some(RaycastHit::Ground {
+                point: Vec3::new(10.5, 2.0, 0.5),
+                distance: 3.0,
+            })
+        );
+
+        // Not from below
+        let hit = raycast(&world, ray(Vec3::new(10.5, 0.0, 0.5), Vec3::Y), 100.0);
+        assert_eq!(hit, None);
+
+        // An empty world has its ground at 0
+        let hit = raycast(
+            &BloxWorld::empty(),
+            ray(Vec3::new(0.0, 1.0, 0.0), -Vec3::Y),
+            100.0,
+        );
+        assert_eq!(hit.map(|hit| hit.point()), Some(Vec3::ZERO));
+    }
+}
@@ -0,0 +1,168 @@
+//! Editing boxes of blocks at once.
+//!
+//! Region operations write all blocks first and then mark the chunks touching the region dirty,
+//! instead of marking the neighbors of every single block. Each operation is one undo step, which
+//! is undone and redone the same way.
+
+use super::{Block, BloxScene, BloxWorld};
+use bevy::prelude::*;
+
+/// Box of blocks, with inclusive min and exclusive max corner like [`BloxScene::bounds`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Region {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl Region {
+    /// Smallest region containing the blocks at `a` and `b`.
+    pub fn from_corners(a: IVec3, b: IVec3) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b) + 1,
+        }
+    }
+
+    pub fn size(&self) -> IVec3 {
+        (self.max - self.min).max(IVec3::ZERO)
+    }
+
+    /// Number of blocks in the region.
+    pub fn volume(&self) -> u64 {
+        let size = self.size().as_u64vec3();
+        size.x * size.y * size.z
+    }
+
+    pub fn contains(&self, pos: IVec3) -> bool {
+        pos.cmpge(self.min).all() && pos.cmplt(self.max).all()
+    }
+
+    /// Whether `pos` is in the region and on its outer shell.
+    pub fn is_on_shell(&self, pos: IVec3) -> bool {
+        self.contains(pos) && (pos.cmpeq(self.min).any() || (pos + 1).cmpeq(self.max).any())
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = IVec3> + use<> {
+        let (min, max) = (self.min, self.max);
+        (min.z..max.z).flat_map(move |z| {
+            (min.y..max.y).flat_map(move |y| (min.x..max.x).map(move |x| IVec3::new(x, y, z)))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl BloxWorld {
+    pub fn fill_region(&mut self, region: Region, block: Block) {
+        self.edit_region(region, |_, _| block);
+    }
+
+    pub fn clear_region(&mut self, region: Region) {
+        self.fill_region(region, Block::AIR);
+    }
+
+    /// Replace every `from` block in the region with `to`.
+    pub fn replace_in_region(&mut self, region: Region, from: Block, to: Block) {
+        self.edit_region(region, |_, old| match old == from {
+            true => to,
+            false => old,
+        });
+    }
+
+    /// Turn the region into a hollow box, its shell made of `block` and its inside cleared.
+    pub fn hollow_region(&mut self, region: Region, block: Block) {
+        self.edit_region(region, |pos, _| match region.is_on_shell(pos) {
+            true => block,
+            false => Block::AIR,
+        });
+    }
+
+    /// Blocks of the region, moved so the min corner of the region is at the origin.
+    pub fn copy_region(&self, region: Region) -> BloxScene {
+        let mut scene = BloxScene::empty();
+        for pos in region.positions() {
+            scene.set_block(pos - region.min, self.block(pos));
+        }
+        scene
+    }
+
+    /// Place the blocks of `scene` moved by `offset`. Air in `scene` leaves the world unchanged,
+    /// so pasting never removes blocks.
+    pub fn paste(&mut self, scene: &BloxScene, offset: IVec3) {
+        let Some((min, max)) = scene.bounds() else {
+            return;
+        };
+        let region = Region {
+            min: min + offset,
+            max: max + offset,
+        };
+        self.edit_region(region, |pos, old| match scene.block(pos - offset) {
+            Block::AIR => old,
+            block => block,
+        });
+    }
+
+    /// Set every block of the region to what `edit` returns for its position and current block,
+    /// as one undo step.
+    fn edit_region(&mut self, region: Region, mut edit: impl FnMut(IVec3, Block) -> Block) {
+        let mut blocks = Vec::new();
+        for pos in region.positions() {
+            let old = self.scene.block(pos);
+            let new = edit(pos, old);
+            if old != new {
+                self.store_block(pos, new);
+                blocks.push((pos, old, new));
+            }
+        }
+
+        if !blocks.is_empty() {
+            self.history.record_region(region, blocks);
+            self.dirty.push_box(region.min, region.max);
+        }
+    }
+}
+
+impl BloxScene {
+    /// Rotated by `quarter_turns` times 90° counterclockwise seen from above, keeping the min
+    /// corner of the bounds in place.
+    pub fn rotated(&self, quarter_turns: i32) -> BloxScene {
+        self.transformed(|pos| match quarter_turns.rem_euclid(4) {
+            0 => pos,
+            1 => IVec3::new(pos.z, pos.y, -pos.x - 1),
+            2 => IVec3::new(-pos.x - 1, pos.y, -pos.z - 1),
+            _ => IVec3::new(-pos.z - 1, pos.y, pos.x),
+        })
+    }
+
+    /// Mirrored along `axis`, keeping the min corner of the bounds in place.
+    pub fn mirrored(&self, axis: Axis) -> BloxScene {
+        self.transformed(|mut pos| {
+            let index = axis as usize;
+            pos[index] = -pos[index] - 1;
+            pos
+        })
+    }
+
+    fn transformed(&self, transform: impl Fn(IVec3) -> IVec3) -> BloxScene {
+        let Some((min, _)) = self.bounds() else {
+            return BloxScene::empty();
+        };
+
+        let mut transformed = BloxScene::empty();
+        for (pos, block) in self.blocks() {
+            transformed.set_block(transform(pos), block);
+        }
+
+        let (transformed_min, _) = transformed.bounds().unwrap();
+        let mut scene = BloxScene::empty();
+        for (pos, block) in transformed.blocks() {
+            scene.set_block(pos - transformed_min + min, block);
+        }
+        scene
+    }
+}
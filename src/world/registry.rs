@@ -0,0 +1,428 @@
+//! Block types, defined in `assets/blocks.registry.ron`.
+//!
+//! Every entry has an id, a name, textures for the top, bottom and side faces, whether it is solid
+//! and transparent, and the material used by the ray tracer. Texture layers are assigned in order
+//! of first use, so the mesh, the block shader and the ray tracer all index the same textures.
+//!
+//! The registry is loaded as an asset in the game, together with its textures. Systems use the
+//! [`CurrentBlockRegistry`], which is replaced once the asset is loaded. Until then it is the
+//! registry built into the binary. Code outside of the ECS, like loading scene files, takes the
+//! registry as an argument.
+//!
+//! Changes to the registry file or its textures are picked up while the game runs if the asset
+//! file watcher is enabled, which sends [`BlockRegistryChanged`]. A reloaded registry whose
+//! textures don't fit into one array texture is rejected and the previous one is kept.
+
+use super::{Block, WorldAssets};
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    platform::collections::HashMap,
+    prelude::*,
+    render::render_resource::TextureFormat,
+};
+use serde::Deserialize;
+use std::{fmt, io, sync::Arc};
+
+pub fn plugin(app: &mut App) {
+    // Assets
+    app.insert_resource(CurrentBlockRegistry(Arc::new(BlockRegistry::builtin())));
+    app.init_asset::<BlockRegistry>();
+    app.register_asset_loader(BlockRegistryLoader);
+
+    // Update
+    app.add_event::<BlockRegistryChanged>();
+    app.add_systems(
+        PreUpdate,
+        reload_registry.run_if(resource_exists::<WorldAssets>),
+    );
+}
+
+/// Sent after the registry asset or one of its textures changed and the
+/// [`CurrentBlockRegistry`] was replaced.
+#[derive(Event, Debug)]
+pub struct BlockRegistryChanged;
+
+/// The registry in use by the game.
+#[derive(Resource, Debug, Clone, Deref)]
+pub struct CurrentBlockRegistry(pub Arc<BlockRegistry>);
+
+/// All block types by id. Id 0 is always air.
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct BlockRegistry {
+    /// Indexed by block id, `None` for unused ids.
+    definitions: Vec<Option<BlockDefinition>>,
+    texture_paths: Vec<String>,
+    /// Images of the texture paths, only loaded when the registry is loaded as an asset.
+    #[dependency]
+    images: Vec<Handle<Image>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockDefinition {
+    /// Stable name, used to identify blocks in files.
+    pub name: String,
+    /// Solid blocks fill the whole block. Other blocks are liquids, whose surface is lowered if
+    /// there is a different block above.
+    pub solid: bool,
+    /// Transparent blocks don't hide the faces of other blocks behind them.
+    pub transparent: bool,
+    pub material: BlockMaterial,
+    /// Texture layers of the top, bottom and side faces.
+    layers: [usize; 3],
+    /// Texture layer of [`BlockMaterial::albedo_texture`].
+    albedo_layer: Option<usize>,
+}
+
+impl BlockDefinition {
+    /// Texture layer of the face facing `normal`, an index into [`BlockRegistry::texture_paths`].
+    pub fn texture_layer(&self, normal: IVec3) -> usize {
+        match normal.y {
+            1 => self.layers[0],
+            -1 => self.layers[1],
+            _ => self.layers[2],
+        }
+    }
+
+    /// Texture layer with the albedo of the face facing `normal`, used by the ray tracer.
+    pub fn albedo_layer(&self, normal: IVec3) -> usize {
+        self.albedo_layer
+            .unwrap_or_else(|| self.texture_layer(normal))
+    }
+}
+
+/// How a block is rendered.
+///
+/// Roughness, metallic and reflectance have the meaning of Bevy's `StandardMaterial`, and are
+/// used by the block shader and the ray tracer alike. The other fields only affect the ray tracer.
+/// Reflectivity and transparency only apply where the albedo texture isn't opaque, see the
+/// registry file. Transparent blocks refract light and reflect it by the Fresnel equations
+/// instead of their reflectivity.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BlockMaterial {
+    /// Texture relative to the assets folder, instead of the textures of the faces.
+    pub albedo_texture: Option<String>,
+    /// Perceptual roughness, from 0 for a mirror to 1 for a matte surface.
+    pub roughness: f32,
+    /// 0 for dielectrics, 1 for metals, which tint their reflections by the albedo.
+    pub metallic: f32,
+    /// Specular reflectance of dielectrics, 0.5 for 4% at normal incidence.
+    pub reflectance: f32,
+    /// Fraction of the light reflected like a mirror.
+    pub reflectivity: f32,
+    pub index_of_refraction: f32,
+    /// Fraction of the light passing through the block.
+    pub transparency: f32,
+    /// Emitted radiance in linear RGB, can be above 1. Emitting blocks are area lights in the
+    /// ray tracer and have this color instead of their texture.
+    pub emission: [f32; 3],
+}
+
+impl Default for BlockMaterial {
+    fn default() -> Self {
+        Self {
+            albedo_texture: None,
+            roughness: 1.0,
+            metallic: 0.0,
+            reflectance: 0.5,
+            reflectivity: 0.0,
+            index_of_refraction: 1.5,
+            transparency: 0.0,
+            emission: [0.0; 3],
+        }
+    }
+}
+
+impl BlockRegistry {
+    /// The registry from `assets/blocks.registry.ron` at compile time.
+    pub fn builtin() -> Self {
+        Self::from_ron(include_str!("../../assets/blocks.registry.ron"))
+            .expect("built-in block registry is valid")
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, BlockRegistryError> {
+        let file = ron::from_str::<RegistryFile>(text)
+            .map_err(|err| BlockRegistryError::Parse(err.to_string()))?;
+
+        let mut registry = Self {
+            definitions: vec![Some(BlockDefinition {
+                name: "air".to_string(),
+                solid: false,
+                transparent: true,
+                material: BlockMaterial::default(),
+                layers: [0; 3],
+                albedo_layer: None,
+            })],
+            texture_paths: Vec::new(),
+            images: Vec::new(),
+        };
+        let mut layers = HashMap::<String, usize>::new();
+        for entry in file.blocks {
+            let id = entry.id as usize;
+            if registry.definitions.get(id).is_some_and(Option::is_some) {
+                return Err(BlockRegistryError::DuplicateId(entry.id));
+            }
+            if registry.find(&entry.name).is_some() {
+                return Err(BlockRegistryError::DuplicateName(entry.name));
+            }
+
+            let (top, bottom, side) = match entry.textures {
+                FaceTextures::All(path) => (path.clone(), path.clone(), path),
+                FaceTextures::Faces { top, bottom, side } => (top, bottom, side),
+            };
+            let mut layer = |path: String| {
+                *layers.entry(path).or_insert_with_key(|path| {
+                    registry.texture_paths.push(path.clone());
+                    registry.texture_paths.len() - 1
+                })
+            };
+            let definition = BlockDefinition {
+                name: entry.name,
+                solid: entry.solid,
+                transparent: entry.transparent,
+                layers: [layer(top), layer(bottom), layer(side)],
+                albedo_layer: entry.material.albedo_texture.clone().map(&mut layer),
+                material: entry.material,
+            };
+
+            if registry.definitions.len() <= id {
+                registry.definitions.resize(id + 1, None);
+            }
+            registry.definitions[id] = Some(definition);
+        }
+
+        Ok(registry)
+    }
+
+    /// Definition of `block`, `None` if its id is unused.
+    pub fn get(&self, block: Block) -> Option<&BlockDefinition> {
+        self.definitions.get(block.id() as usize)?.as_ref()
+    }
+
+    /// Definition of `block`, the one of air if its id is unused.
+    pub fn definition(&self, block: Block) -> &BlockDefinition {
+        self.get(block)
+            .or(self.definitions[0].as_ref())
+            .expect("air is always defined")
+    }
+
+    /// All blocks other than air, in id order.
+    pub fn blocks(&self) -> impl Iterator<Item = (Block, &BlockDefinition)> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter_map(|(id, definition)| Some((Block::from_id(id as u8), definition.as_ref()?)))
+    }
+
+    /// Block with the name `name`, air included.
+    pub fn find(&self, name: &str) -> Option<Block> {
+        self.definitions
+            .iter()
+            .position(|definition| definition.as_ref().is_some_and(|d| d.name == name))
+            .map(|id| Block::from_id(id as u8))
+    }
+
+    /// Texture paths relative to the assets folder, in texture layer order.
+    pub fn texture_paths(&self) -> &[String] {
+        &self.texture_paths
+    }
+
+    /// Images of [`BlockRegistry::texture_paths`], empty if the registry wasn't loaded as an
+    /// asset.
+    pub fn images(&self) -> &[Handle<Image>] {
+        &self.images
+    }
+
+    /// Check that the loaded [images](BlockRegistry::images) are sRGB RGBA8 and all have the
+    /// same square size, so they can be layers of one array texture.
+    pub fn validate_images(&self, images: &Assets<Image>) -> Result<(), BlockRegistryError> {
+        let mut expected = None;
+        for (path, handle) in self.texture_paths.iter().zip(&self.images) {
+            let image = images
+                .get(handle)
+                .filter(|image| {
+                    image.texture_descriptor.format == TextureFormat::Rgba8UnormSrgb
+                        && image.data.is_some()
+                })
+                .ok_or_else(|| BlockRegistryError::TextureFormat(path.clone()))?;
+
+            let expected = *expected.get_or_insert(UVec2::splat(image.width()));
+            if image.size() != expected {
+                return Err(BlockRegistryError::TextureSize {
+                    path: path.clone(),
+                    size: image.size(),
+                    expected,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl BlockRegistry {
+    /// Registry using every block id, named after their ids.
+    pub(super) fn with_all_ids() -> Self {
+        let blocks = (1..=u8::MAX)
+            .map(|id| format!("(id: {id}, name: \"block_{id}\", textures: \"block.png\")"))
+            .collect::<Vec<_>>();
+        Self::from_ron(&format!("(blocks: [{}])", blocks.join(", "))).unwrap()
+    }
+}
+
+#[derive(Deserialize)]
+struct RegistryFile {
+    blocks: Vec<BlockEntry>,
+}
+
+#[derive(Deserialize)]
+struct BlockEntry {
+    id: u8,
+    name: String,
+    textures: FaceTextures,
+    #[serde(default = "default_solid")]
+    solid: bool,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    material: BlockMaterial,
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+/// One texture for all faces, or one each for the top, bottom and side faces.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FaceTextures {
+    All(String),
+    Faces {
+        top: String,
+        bottom: String,
+        side: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum BlockRegistryError {
+    Io(io::Error),
+    Parse(String),
+    DuplicateId(u8),
+    DuplicateName(String),
+    /// The texture at the path isn't an sRGB RGBA8 image.
+    TextureFormat(String),
+    TextureSize {
+        path: String,
+        size: UVec2,
+        expected: UVec2,
+    },
+}
+
+impl fmt::Display for BlockRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Parse(err) => write!(f, "{err}"),
+            Self::DuplicateId(id) => write!(f, "block id {id} is used twice"),
+            Self::DuplicateName(name) => write!(f, "block name {name:?} is used twice"),
+            Self::TextureFormat(path) => write!(f, "texture {path:?} is not an sRGB RGBA8 image"),
+            Self::TextureSize {
+                path,
+                size,
+                expected,
+            } => write!(
+                f,
+                "texture {path:?} is {}x{}, expected {}x{} like the other textures",
+                size.x, size.y, expected.x, expected.y
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BlockRegistryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BlockRegistryError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Install the registry asset as the current registry once it and its textures are reloaded.
+/// Blocks keep their ids, so blocks missing from the new registry behave like air.
+fn reload_registry(
+    mut registry_events: EventReader<AssetEvent<BlockRegistry>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    mut changed_events: EventWriter<BlockRegistryChanged>,
+    mut is_pending: Local<bool>,
+    mut current: ResMut<CurrentBlockRegistry>,
+    world_assets: Res<WorldAssets>,
+    registries: Res<Assets<BlockRegistry>>,
+    images: Res<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+) {
+    let handle = &world_assets.block_registry;
+    let Some(registry) = registries.get(handle) else {
+        return;
+    };
+
+    for event in registry_events.read() {
+        *is_pending |= event.is_modified(handle);
+    }
+    for event in image_events.read() {
+        *is_pending |= registry.images.iter().any(|image| event.is_modified(image));
+    }
+
+    // New textures of a modified registry may still be loading
+    if *is_pending && asset_server.is_loaded_with_dependencies(handle) {
+        *is_pending = false;
+        if let Err(err) = registry.validate_images(&images) {
+            log::error!("Keeping the previous block registry: {err}");
+            return;
+        }
+        current.0 = Arc::new(registry.clone());
+        changed_events.write(BlockRegistryChanged);
+        log::info!("Reloaded the block registry");
+    }
+}
+
+/// Loads `.registry.ron` files, together with the images of their textures.
+struct BlockRegistryLoader;
+
+impl AssetLoader for BlockRegistryLoader {
+    type Asset = BlockRegistry;
+    type Settings = ();
+    type Error = BlockRegistryError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<BlockRegistry, BlockRegistryError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let text =
+            String::from_utf8(bytes).map_err(|err| BlockRegistryError::Parse(err.to_string()))?;
+
+        let mut registry = BlockRegistry::from_ron(&text)?;
+        registry.images = registry
+            .texture_paths
+            .iter()
+            .map(|path| load_context.load(path.clone()))
+            .collect();
+        Ok(registry)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["registry.ron"]
+    }
+}
@@ -0,0 +1,656 @@
+//! Minecraft schematic import, Sponge `.schem` (versions 1 to 3) and Litematica `.litematic`.
+//!
+//! Both are gzip compressed NBT. Block states are mapped to blocks by their id without namespace
+//! and properties, so `minecraft:oak_log[axis=y]` becomes `oak_log`. Ids without a block in
+//! [`SchematicImportOptions`] are replaced by its default block and reported in
+//! [`SchematicImport::unknown_ids`].
+
+use super::{Block, BloxScene, DEFAULT_MAX_IMPORT_SIZE, SceneFileError, binary::Reader};
+use bevy::{math::I64Vec3, platform::collections::HashMap, prelude::*};
+use std::{collections::BTreeSet, io::Read};
+
+#[derive(Debug, Clone)]
+pub struct SchematicImportOptions {
+    pub ids: HashMap<String, Block>,
+    /// Block for ids that are not in `ids`.
+    pub default_block: Block,
+    /// Larger schematics fail with [`SceneFileError::RegionTooLarge`].
+    pub max_size: UVec3,
+}
+
+impl Default for SchematicImportOptions {
+    fn default() -> Self {
+        let ids = [
+            ("air", Block::AIR),
+            ("cave_air", Block::AIR),
+            ("void_air", Block::AIR),
+            ("dirt", Block::DIRT),
+            ("stone", Block::STONE),
+            ("sand", Block::SAND),
+            ("grass_block", Block::GRASS),
+            ("oak_log", Block::WOOD),
+            ("oak_leaves", Block::LEAVES),
+            ("water", Block::WATER),
+            ("glass", Block::GLASS),
+            ("glowstone", Block::LAMP),
+        ];
+        Self {
+            ids: ids
+                .into_iter()
+                .map(|(id, block)| (id.to_string(), block))
+                .collect(),
+            default_block: Block::STONE,
+            max_size: DEFAULT_MAX_IMPORT_SIZE,
+        }
+    }
+}
+
+impl SchematicImportOptions {
+    /// Map a Minecraft id, without `minecraft:` namespace, to a block.
+    pub fn with_id(mut self, id: impl Into<String>, block: Block) -> Self {
+        self.ids.insert(id.into(), block);
+        self
+    }
+
+    pub fn with_default_block(mut self, block: Block) -> Self {
+        self.default_block = block;
+        self
+    }
+
+    pub fn with_max_size(mut self, max_size: UVec3) -> Self {
+        self.max_size = max_size;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SchematicImport {
+    pub scene: BloxScene,
+    /// Ids that were replaced by the default block, sorted.
+    pub unknown_ids: Vec<String>,
+}
+
+impl BloxScene {
+    pub fn from_schematic(
+        bytes: &[u8],
+        options: &SchematicImportOptions,
+    ) -> Result<SchematicImport, SceneFileError> {
+        let mut decompressed = Vec::new();
+        let bytes = match bytes.starts_with(&[0x1F, 0x8B]) {
+            true => {
+                flate2::read::GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+                &decompressed
+            }
+            false => bytes,
+        };
+
+        let mut reader = Reader { bytes };
+        if reader.byte()? != TAG_COMPOUND {
+            return Err(SceneFileError::Parse(
+                "root tag is not a compound".to_string(),
+            ));
+        }
+        read_string(&mut reader)?;
+        let root = read_payload(&mut reader, TAG_COMPOUND, 0)?;
+
+        let regions = match root.get("Regions") {
+            Some(regions) => litematic_regions(regions, options.max_size)?,
+            None => vec![sponge_region(
+                root.get("Schematic").unwrap_or(&root),
+                options.max_size,
+            )?],
+        };
+
+        // Place the regions relative to the corner of their bounding box
+        let min = regions.iter().map(|region| region.min).reduce(IVec3::min);
+        let max = regions
+            .iter()
+            .map(|region| region.min.as_i64vec3() + region.size.as_i64vec3())
+            .reduce(I64Vec3::max);
+        let (Some(min), Some(max)) = (min, max) else {
+            return Err(SceneFileError::MissingTag("Regions"));
+        };
+        let size = (max - min.as_i64vec3()).min(I64Vec3::splat(u32::MAX as i64));
+        check_size(size.as_uvec3(), options.max_size)?;
+
+        let mut scene = BloxScene::empty();
+        let mut unknown_ids = BTreeSet::new();
+        for region in regions {
+            let blocks = region
+                .palette
+                .iter()
+                .map(|state| {
+                    let id = state.split('[').next().unwrap();
+                    let id = id.strip_prefix("minecraft:").unwrap_or(id);
+                    options.ids.get(id).copied().unwrap_or_else(|| {
+                        unknown_ids.insert(id.to_string());
+                        options.default_block
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let size = region.size.as_ivec3();
+            for (i, &index) in region.indices.iter().enumerate() {
+                let Some(&block) = blocks.get(index) else {
+                    return Err(SceneFileError::Parse(format!(
+                        "invalid palette index {index}"
+                    )));
+                };
+                let i = i as i32;
+                let pos = IVec3::new(i % size.x, i / (size.x * size.z), i / size.x % size.z);
+                scene.set_block(region.min - min + pos, block);
+            }
+        }
+
+        Ok(SchematicImport {
+            scene,
+            unknown_ids: unknown_ids.into_iter().collect(),
+        })
+    }
+}
+
+/// Blocks of a box, in x, z, y order (x changing fastest).
+struct Region {
+    min: IVec3,
+    size: UVec3,
+    palette: Vec<String>,
+    indices: Vec<usize>,
+}
+
+fn sponge_region(schematic: &Tag, max_size: UVec3) -> Result<Region, SceneFileError> {
+    let size = UVec3::new(
+        schematic.short("Width")? as u16 as u32,
+        schematic.short("Height")? as u16 as u32,
+        schematic.short("Length")? as u16 as u32,
+    );
+    check_size(size, max_size)?;
+
+    // Version 3 moved the palette and block data into a Blocks compound
+    let (palette, data) = match schematic.get("Blocks") {
+        Some(blocks) => (blocks.tag("Palette")?, blocks.tag("Data")?),
+        None => (schematic.tag("Palette")?, schematic.tag("BlockData")?),
+    };
+    let Tag::Compound(palette) = palette else {
+        return Err(SceneFileError::MissingTag("Palette"));
+    };
+    let Tag::ByteArray(data) = data else {
+        return Err(SceneFileError::MissingTag("BlockData"));
+    };
+
+    let mut states = vec![String::new(); palette.len()];
+    for (state, index) in palette {
+        let Tag::Int(index) = *index else {
+            return Err(SceneFileError::Parse(format!(
+                "invalid palette entry {state}"
+            )));
+        };
+        let Some(entry) = states.get_mut(index as usize) else {
+            return Err(SceneFileError::Parse(format!(
+                "invalid palette index {index}"
+            )));
+        };
+        *entry = state.clone();
+    }
+
+    let mut reader = Reader { bytes: data };
+    let indices = (0..size.element_product())
+        .map(|_| Ok(reader.varint()? as usize))
+        .collect::<Result<Vec<_>, SceneFileError>>()?;
+
+    Ok(Region {
+        min: IVec3::ZERO,
+        size,
+        palette: states,
+        indices,
+    })
+}
+
+fn litematic_regions(regions: &Tag, max_size: UVec3) -> Result<Vec<Region>, SceneFileError> {
+    let Tag::Compound(regions) = regions else {
+        return Err(SceneFileError::MissingTag("Regions"));
+    };
+
+    let vec3 = |tag: &Tag| -> Result<IVec3, SceneFileError> {
+        Ok(IVec3::new(tag.int("x")?, tag.int("y")?, tag.int("z")?))
+    };
+    regions
+        .values()
+        .map(|region| {
+            // Negative sizes extend from the position towards negative coordinates
+            let position = vec3(region.tag("Position")?)?;
+            let signed_size = vec3(region.tag("Size")?)?;
+            let size = UVec3::new(
+                signed_size.x.unsigned_abs(),
+                signed_size.y.unsigned_abs(),
+                signed_size.z.unsigned_abs(),
+            );
+            check_size(size, max_size)?;
+            let min = position.as_i64vec3() + (signed_size.as_i64vec3() + 1).min(I64Vec3::ZERO);
+            let max = min + size.as_i64vec3();
+            if min.cmplt(I64Vec3::splat(i32::MIN as i64)).any()
+                || max.cmpgt(I64Vec3::splat(i32::MAX as i64)).any()
+            {
+                return Err(SceneFileError::RegionOutOfBounds {
+                    min: position,
+                    size,
+                });
+            }
+            let min = min.as_ivec3();
+
+            let Tag::List(palette) = region.tag("BlockStatePalette")? else {
+                return Err(SceneFileError::MissingTag("BlockStatePalette"));
+            };
+            let palette = palette
+                .iter()
+                .map(|state| match state.tag("Name")? {
+                    Tag::String(name) => Ok(name.clone()),
+                    _ => Err(SceneFileError::MissingTag("Name")),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let Tag::LongArray(states) = region.tag("BlockStates")? else {
+                return Err(SceneFileError::MissingTag("BlockStates"));
+            };
+
+            // Indices are bit packed and may span two longs
+            let bits = (usize::BITS - palette.len().saturating_sub(1).leading_zeros()).max(2);
+            let mask = (1u64 << bits) - 1;
+            let indices = (0..size.element_product() as usize)
+                .map(|i| {
+                    let start = i * bits as usize;
+                    let end = start + bits as usize - 1;
+                    let (word, offset) = (start / 64, start % 64);
+                    let low = *states.get(word).ok_or(SceneFileError::UnexpectedEnd)? as u64;
+                    let mut value = low >> offset;
+                    if end / 64 != word {
+                        let high = *states.get(end / 64).ok_or(SceneFileError::UnexpectedEnd)?;
+                        value |= (high as u64) << (64 - offset);
+                    }
+                    Ok((value & mask) as usize)
+                })
+                .collect::<Result<Vec<_>, SceneFileError>>()?;
+
+            Ok(Region {
+                min,
+                size,
+                palette,
+                indices,
+            })
+        })
+        .collect()
+}
+
+fn check_size(size: UVec3, max: UVec3) -> Result<(), SceneFileError> {
+    match size.cmpgt(max).any() {
+        true => Err(SceneFileError::RegionTooLarge { size, max }),
+        false => Ok(()),
+    }
+}
+
+const TAG_END: u8 = 0;
+const TAG_COMPOUND: u8 = 10;
+
+/// Deeper nesting is rejected, so malformed files can't overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+/// NBT tag, types the importers don't need are skipped.
+#[derive(Debug)]
+enum Tag {
+    Short(i16),
+    Int(i32),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    LongArray(Vec<i64>),
+    Other,
+}
+
+impl Tag {
+    fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(tags) => tags.get(name),
+            _ => None,
+        }
+    }
+
+    fn tag(&self, name: &'static str) -> Result<&Tag, SceneFileError> {
+        self.get(name).ok_or(SceneFileError::MissingTag(name))
+    }
+
+    fn short(&self, name: &'static str) -> Result<i16, SceneFileError> {
+        match self.tag(name)? {
+            Tag::Short(value) => Ok(*value),
+            _ => Err(SceneFileError::MissingTag(name)),
+        }
+    }
+
+    fn int(&self, name: &'static str) -> Result<i32, SceneFileError> {
+        match self.tag(name)? {
+            Tag::Int(value) => Ok(*value),
+            _ => Err(SceneFileError::MissingTag(name)),
+        }
+    }
+}
+
+fn read_payload(reader: &mut Reader, id: u8, depth: usize) -> Result<Tag, SceneFileError> {
+    if depth > MAX_DEPTH {
+        return Err(SceneFileError::Parse(
+            "tags are nested too deeply".to_string(),
+        ));
+    }
+
+    // Lengths are signed, treat negative ones as empty
+    let length = |reader: &mut Reader| -> Result<usize, SceneFileError> {
+        Ok(i32::from_be_bytes(reader.array()?).max(0) as usize)
+    };
+
+    Ok(match id {
+        1 => {
+            reader.take(1)?;
+            Tag::Other
+        }
+        2 => Tag::Short(i16::from_be_bytes(reader.array()?)),
+        3 => Tag::Int(i32::from_be_bytes(reader.array()?)),
+        4 | 6 => {
+            reader.take(8)?;
+            Tag::Other
+        }
+        5 => {
+            reader.take(4)?;
+            Tag::Other
+        }
+        7 => {
+            let length = length(reader)?;
+            Tag::ByteArray(reader.take(length)?.to_vec())
+        }
+        8 => Tag::String(read_string(reader)?),
+        9 => {
+            let id = reader.byte()?;
+            let length = length(reader)?;
+            Tag::List(
+                (0..length)
+                    .map(|_| read_payload(reader, id, depth + 1))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        TAG_COMPOUND => {
+            let mut tags = HashMap::default();
+            loop {
+                let id = reader.byte()?;
+                if id == TAG_END {
+                    break;
+                }
+                let name = read_string(reader)?;
+                tags.insert(name, read_payload(reader, id, depth + 1)?);
+            }
+            Tag::Compound(tags)
+        }
+        11 => {
+            let length = length(reader)?;
+            reader.take(length.saturating_mul(4))?;
+            Tag::Other
+        }
+        12 => {
+            let length = length(reader)?;
+            Tag::LongArray(
+                (0..length)
+                    .map(|_| Ok(i64::from_be_bytes(reader.array()?)))
+                    .collect::<Result<_, SceneFileError>>()?,
+            )
+        }
+        _ => return Err(SceneFileError::Parse(format!("unknown tag type {id}"))),
+    })
+}
+
+fn read_string(reader: &mut Reader) -> Result<String, SceneFileError> {
+    let length = u16::from_be_bytes(reader.array()?) as usize;
+    Ok(String::from_utf8_lossy(reader.take(length)?).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hand built NBT, the helpers return named tags
+    fn named(id: u8, name: &str, payload: &[u8]) -> Vec<u8> {
+        [
+            &[id],
+            &(name.len() as u16).to_be_bytes()[..],
+            name.as_bytes(),
+            payload,
+        ]
+        .concat()
+    }
+
+    fn compound_payload(tags: &[Vec<u8>]) -> Vec<u8> {
+        [tags.concat(), vec![TAG_END]].concat()
+    }
+
+    fn compound(name: &str, tags: &[Vec<u8>]) -> Vec<u8> {
+        named(TAG_COMPOUND, name, &compound_payload(tags))
+    }
+
+    fn short(name: &str, value: i16) -> Vec<u8> {
+        named(2, name, &value.to_be_bytes())
+    }
+
+    fn int(name: &str, value: i32) -> Vec<u8> {
+        named(3, name, &value.to_be_bytes())
+    }
+
+    fn byte_array(name: &str, bytes: &[u8]) -> Vec<u8> {
+        let length = (bytes.len() as i32).to_be_bytes();
+        named(7, name, &[&length[..], bytes].concat())
+    }
+
+    fn string(name: &str, value: &str) -> Vec<u8> {
+        let length = (value.len() as u16).to_be_bytes();
+        named(8, name, &[&length[..], value.as_bytes()].concat())
+    }
+
+    fn long_array(name: &str, values: &[i64]) -> Vec<u8> {
+        let mut payload = (values.len() as i32).to_be_bytes().to_vec();
+        payload.extend(values.iter().flat_map(|value| value.to_be_bytes()));
+        named(12, name, &payload)
+    }
+
+    fn vec3(name: &str, value: IVec3) -> Vec<u8> {
+        compound(
+            name,
+            &[int("x", value.x), int("y", value.y), int("z", value.z)],
+        )
+    }
+
+    fn sponge_palette(ids: &[&str]) -> Vec<u8> {
+        let entries = ids
+            .iter()
+            .enumerate()
+            .map(|(index, id)| int(id, index as i32))
+            .collect::<Vec<_>>();
+        compound("Palette", &entries)
+    }
+
+    fn litematic_region(
+        name: &str,
+        position: IVec3,
+        size: IVec3,
+        palette: &[&str],
+        states: &[i64],
+    ) -> Vec<u8> {
+        let mut list = vec![TAG_COMPOUND];
+        list.extend((palette.len() as i32).to_be_bytes());
+        for id in palette {
+            list.extend(compound_payload(&[string("Name", id)]));
+        }
+        compound(
+            name,
+            &[
+                vec3("Position", position),
+                vec3("Size", size),
+                named(9, "BlockStatePalette", &list),
+                long_array("BlockStates", states),
+            ],
+        )
+    }
+
+    fn import(tags: &[Vec<u8>]) -> Result<SchematicImport, SceneFileError> {
+        BloxScene::from_schematic(&compound("", tags), &SchematicImportOptions::default())
+    }
+
+    #[test]
+    fn sponge_blocks_are_in_x_z_y_order_with_varint_indices() {
+        // Index 128 takes two bytes as varint
+        let mut ids = vec![
+            "minecraft:air",
+            "minecraft:stone",
+            "minecraft:dirt",
+            "glass",
+        ];
+        let fillers = (ids.len()..128)
+            .map(|i| format!("minecraft:filler_{i}"))
+            .collect::<Vec<_>>();
+        ids.extend(fillers.iter().map(String::as_str));
+        ids.push("minecraft:sand");
+
+        let import = import(&[
+            short("Width", 2),
+            short("Height", 2),
+            short("Length", 2),
+            sponge_palette(&ids),
+            byte_array("BlockData", &[1, 0x80, 0x01, 0, 3, 0, 0, 2, 0]),
+        ])
+        .unwrap();
+
+        let scene = import.scene;
+        assert_eq!(scene.blocks().count(), 4);
+        assert_eq!(scene.block(IVec3::new(0, 0, 0)), Block::STONE);
+        assert_eq!(scene.block(IVec3::new(1, 0, 0)), Block::SAND);
+        assert_eq!(scene.block(IVec3::new(1, 0, 1)), Block::GLASS);
+        assert_eq!(scene.block(IVec3::new(0, 1, 1)), Block::DIRT);
+    }
+
+    #[test]
+    fn sponge_v3_blocks_compound_is_read() {
+        let import = import(&[compound(
+            "Schematic",
+            &[
+                int("Version", 3),
+                short("Width", 1),
+                short("Height", 1),
+                short("Length", 3),
+                compound(
+                    "Blocks",
+                    &[
+                        sponge_palette(&[
+                            "minecraft:air",
+                            "minecraft:oak_log[axis=y]",
+                            "minecraft:diamond_block",
+                        ]),
+                        byte_array("Data", &[1, 0, 2]),
+                    ],
+                ),
+            ],
+        )])
+        .unwrap();
+
+        assert_eq!(import.unknown_ids, ["diamond_block"]);
+        let scene = import.scene;
+        assert_eq!(scene.blocks().count(), 2);
+        assert_eq!(scene.block(IVec3::new(0, 0, 0)), Block::WOOD);
+        assert_eq!(scene.block(IVec3::new(0, 0, 2)), Block::STONE);
+    }
+
+    #[test]
+    fn negative_litematic_sizes_extend_towards_negative_coordinates() {
+        let position = IVec3::new(10, 5, -3);
+        let import = import(&[compound(
+            "Regions",
+            &[
+                litematic_region(
+                    "negative",
+                    position,
+                    IVec3::new(-2, 1, 1),
+                    &["minecraft:air", "minecraft:stone"],
+                    &[0b0101],
+                ),
+                litematic_region(
+                    "positive",
+                    position + IVec3::X,
+                    IVec3::ONE,
+                    &["minecraft:air", "minecraft:sand"],
+                    &[0b01],
+                ),
+            ],
+        )])
+        .unwrap();
+
+        // The negative region ends at the position
+        let scene = import.scene;
+        assert_eq!(scene.blocks().count(), 3);
+        assert_eq!(scene.block(IVec3::new(0, 0, 0)), Block::STONE);
+        assert_eq!(scene.block(IVec3::new(1, 0, 0)), Block::STONE);
+        assert_eq!(scene.block(IVec3::new(2, 0, 0)), Block::SAND);
+    }
+
+    #[test]
+    fn litematic_indices_can_span_two_longs() {
+        // With 3 bits per index, index 21 starts at bit 63 of the first long
+        let palette = [
+            "minecraft:air",
+            "minecraft:stone",
+            "minecraft:dirt",
+            "minecraft:grass_block",
+            "minecraft:oak_log",
+            "minecraft:sand",
+            "minecraft:glass",
+            "minecraft:water",
+        ];
+        let states = [1 | (1 << 63), 0b10];
+        let import = import(&[compound(
+            "Regions",
+            &[litematic_region(
+                "region",
+                IVec3::ZERO,
+                IVec3::new(24, 1, 1),
+                &palette,
+                &states,
+            )],
+        )])
+        .unwrap();
+
+        let scene = import.scene;
+        assert_eq!(scene.blocks().count(), 2);
+        assert_eq!(scene.block(IVec3::new(0, 0, 0)), Block::STONE);
+        assert_eq!(scene.block(IVec3::new(21, 0, 0)), Block::SAND);
+    }
+
+    #[test]
+    fn regions_out_of_bounds_are_rejected() {
+        let region = |name, position, size| {
+            litematic_region(name, position, size, &["minecraft:stone"], &[0])
+        };
+
+        for (position, size) in [
+            (IVec3::new(i32::MAX, 0, 0), IVec3::new(2, 1, 1)),
+            (IVec3::new(i32::MIN, 0, 0), IVec3::new(-2, 1, 1)),
+        ] {
+            assert!(matches!(
+                import(&[compound("Regions", &[region("region", position, size)])]),
+                Err(SceneFileError::RegionOutOfBounds { .. })
+            ));
+        }
+
+        // Far apart regions make the whole schematic too large
+        assert!(matches!(
+            import(&[compound(
+                "Regions",
+                &[
+                    region("min", IVec3::splat(i32::MIN), IVec3::ONE),
+                    region("max", IVec3::splat(i32::MAX - 1), IVec3::ONE),
+                ],
+            )]),
+            Err(SceneFileError::RegionTooLarge { .. })
+        ));
+    }
+}
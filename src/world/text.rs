@@ -0,0 +1,291 @@
+//! Human-readable RON scene format, meant for hand-edited scenes and test fixtures.
+//!
+//! ```ron
+//! (
+//!     palette: {
+//!         'G': "grass",
+//!         'S': "stone",
+//!     },
+//!     fills: [
+//!         (min: (0, 0, 0), max: (14, 0, 14), block: "stone"),
+//!     ],
+//!     layers: [
+//!         (y: 1, rows: [
+//!             "GGG..",
+//!             "GG...",
+//!         ]),
+//!     ],
+//! )
+//! ```
+//!
+//! The scene starts out empty. Fills are applied first, with inclusive bounds. Then each layer
+//! sets the blocks of one y level: rows go along z, the symbols of a row along x, and each symbol
+//! is looked up in the palette. `.` is air unless the palette says otherwise. Rows start at the
+//! optional `x` and `z` of the layer, which default to 0. Blocks beyond the end of a row or past
+//! the last row are left as they are. Fills and layers larger than 256 blocks along any axis are
+//! rejected.
+
+use super::{Block, BlockRegistry, BloxScene, DEFAULT_MAX_IMPORT_SIZE, SceneFileError, chunk};
+use bevy::{math::I64Vec3, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TextScene {
+    #[serde(default)]
+    palette: BTreeMap<char, String>,
+    #[serde(default)]
+    fills: Vec<Fill>,
+    #[serde(default)]
+    layers: Vec<Layer>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Fill {
+    min: (i32, i32, i32),
+    max: (i32, i32, i32),
+    block: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Layer {
+    #[serde(default, skip_serializing_if = "is_zero")]
+    x: i32,
+    y: i32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    z: i32,
+    rows: Vec<String>,
+}
+
+fn is_zero(value: &i32) -> bool {
+    *value == 0
+}
+
+impl BloxScene {
+    /// Write the scene in the text format, with the block names of `registry`. Each y level of
+    /// each chunk with blocks becomes a fill if its blocks fill a box, and rows otherwise, so
+    /// blocks far apart don't blow up the file.
+    pub fn to_ron(&self, registry: &BlockRegistry) -> String {
+        let name = |block: Block| registry.definition(block).name.clone();
+
+        // Sorted, so the same scene always gives the same file
+        let mut slices = BTreeMap::<(i32, i32, i32), Vec<(IVec3, Block)>>::new();
+        for (pos, block) in self.blocks() {
+            let chunk = chunk::chunk_coord(pos);
+            slices
+                .entry((pos.y, chunk.z, chunk.x))
+                .or_default()
+                .push((pos, block));
+        }
+        let blocks = slices
+            .values()
+            .flatten()
+            .map(|&(_, block)| block.id())
+            .collect::<BTreeSet<_>>();
+
+        // Assign a symbol to every block in the scene, preferably a letter of its name. Latin
+        // letters with diacritics are enough for the symbols of all 256 block ids.
+        let mut symbols = vec![(Block::AIR, '.')];
+        for block in blocks.into_iter().map(Block::from_id) {
+            let symbol = name(block)
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .map(|c| c.to_ascii_uppercase())
+                .chain('A'..='Z')
+                .chain('0'..='9')
+                .chain('a'..='z')
+                .chain('\u{C0}'..='\u{24F}')
+                .find(|&c| symbols.iter().all(|&(_, s)| s != c))
+                .expect("there are more symbols than block ids");
+            symbols.push((block, symbol));
+        }
+        let symbol = |block: Block| symbols.iter().find(|&&(b, _)| b == block).unwrap().1;
+
+        let mut fills = Vec::new();
+        let mut layers = Vec::new();
+        for ((y, _, _), blocks) in slices {
+            let min = blocks
+                .iter()
+                .fold(IVec3::MAX, |min, &(pos, _)| min.min(pos));
+            let max = blocks
+                .iter()
+                .fold(IVec3::MIN, |max, &(pos, _)| max.max(pos));
+            let width = (max.x - min.x + 1) as usize;
+            let depth = (max.z - min.z + 1) as usize;
+
+            let block = blocks[0].1;
+            if blocks.len() == width * depth && blocks.iter().all(|&(_, b)| b == block) {
+                fills.push(Fill {
+                    min: (min.x, y, min.z),
+                    max: (max.x, y, max.z),
+                    block: name(block),
+                });
+            } else {
+                let mut rows = vec![symbol(Block::AIR); width * depth];
+                for (pos, block) in blocks {
+                    let offset = pos - min;
+                    rows[offset.z as usize * width + offset.x as usize] = symbol(block);
+                }
+                layers.push(Layer {
+                    x: min.x,
+                    y,
+                    z: min.z,
+                    rows: rows.chunks(width).map(String::from_iter).collect(),
+                });
+            }
+        }
+
+        let scene = TextScene {
+            palette: symbols
+                .iter()
+                .filter(|&&(block, _)| block != Block::AIR)
+                .map(|&(block, symbol)| (symbol, name(block)))
+                .collect(),
+            fills,
+            layers,
+        };
+
+        ron::ser::to_string_pretty(&scene, ron::ser::PrettyConfig::new()).unwrap()
+    }
+
+    /// Read a scene in the text format, looking up block names in `registry`.
+    pub fn from_ron(text: &str, registry: &BlockRegistry) -> Result<Self, SceneFileError> {
+        let text_scene = ron::from_str::<TextScene>(text)
+            .map_err(|err| SceneFileError::Parse(err.to_string()))?;
+
+        let block_from_name = |name: &str| {
+            registry
+                .find(name)
+                .ok_or_else(|| SceneFileError::UnknownBlock(name.to_string()))
+        };
+        let mut palette = BTreeMap::from([('.', Block::AIR)]);
+        for (symbol, name) in &text_scene.palette {
+            palette.insert(*symbol, block_from_name(name)?);
+        }
+
+        let mut scene = BloxScene::empty();
+
+        for fill in &text_scene.fills {
+            let block = block_from_name(&fill.block)?;
+            let min = IVec3::from(fill.min);
+            let max = IVec3::from(fill.max);
+            // Inverted bounds are an empty fill
+            check_size((max.as_i64vec3() - min.as_i64vec3() + 1).max(I64Vec3::ZERO))?;
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    for z in min.z..=max.z {
+                        scene.set_block(IVec3::new(x, y, z), block);
+                    }
+                }
+            }
+        }
+
+        for layer in &text_scene.layers {
+            let min = IVec3::new(layer.x, layer.y, layer.z);
+            let width = layer.rows.iter().map(|row| row.chars().count()).max();
+            let size = I64Vec3::new(width.unwrap_or(0) as i64, 1, layer.rows.len() as i64);
+            check_size(size)?;
+            if (min.as_i64vec3() + size)
+                .cmpgt(I64Vec3::splat(i32::MAX as i64 + 1))
+                .any()
+            {
+                return Err(SceneFileError::RegionOutOfBounds {
+                    min,
+                    size: size.as_uvec3(),
+                });
+            }
+
+            for (z, row) in layer.rows.iter().enumerate() {
+                for (x, symbol) in row.chars().enumerate() {
+                    let block = *palette
+                        .get(&symbol)
+                        .ok_or(SceneFileError::UnknownSymbol(symbol))?;
+                    let pos = IVec3::new(layer.x + x as i32, layer.y, layer.z + z as i32);
+                    scene.set_block(pos, block);
+                }
+            }
+        }
+
+        Ok(scene)
+    }
+}
+
+/// Fail for boxes larger than [`DEFAULT_MAX_IMPORT_SIZE`], their sizes come straight from the
+/// file.
+fn check_size(size: I64Vec3) -> Result<(), SceneFileError> {
+    let max = DEFAULT_MAX_IMPORT_SIZE;
+    match size.cmpgt(max.as_i64vec3()).any() {
+        true => Err(SceneFileError::RegionTooLarge {
+            size: size.min(I64Vec3::splat(u32::MAX as i64)).as_uvec3(),
+            max,
+        }),
+        false => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::default_scene;
+
+    #[test]
+    fn scenes_round_trip() {
+        let registry = BlockRegistry::builtin();
+        let scene = default_scene();
+        let loaded = BloxScene::from_ron(&scene.to_ron(&registry), &registry).unwrap();
+        assert_eq!(loaded, scene);
+    }
+
+    #[test]
+    fn far_apart_blocks_round_trip() {
+        let registry = BlockRegistry::builtin();
+        let mut scene = BloxScene::empty();
+        scene.set_block(IVec3::splat(-1_000_000), Block::STONE);
+        scene.set_block(IVec3::new(1_000_000, 5, 1_000_000), Block::SAND);
+        scene.set_block(IVec3::new(1_000_001, 5, 1_000_002), Block::GLASS);
+
+        let text = scene.to_ron(&registry);
+        assert!(text.len() < 1000);
+        assert_eq!(BloxScene::from_ron(&text, &registry).unwrap(), scene);
+    }
+
+    #[test]
+    fn every_block_id_gets_a_symbol() {
+        let registry = BlockRegistry::with_all_ids();
+        let mut scene = BloxScene::empty();
+        for id in 1..=u8::MAX {
+            scene.set_block(
+                IVec3::new(id as i32 % 16, 0, id as i32 / 16),
+                Block::from_id(id),
+            );
+        }
+
+        let loaded = BloxScene::from_ron(&scene.to_ron(&registry), &registry).unwrap();
+        assert_eq!(loaded, scene);
+    }
+
+    #[test]
+    fn oversized_fills_and_layers_are_rejected() {
+        let registry = BlockRegistry::builtin();
+        let load = |text: &str| BloxScene::from_ron(text, &registry);
+
+        let fill = "(fills: [(min: (-1000000, 0, 0), max: (1000000, 0, 0), block: \"stone\")])";
+        assert!(matches!(
+            load(fill),
+            Err(SceneFileError::RegionTooLarge { .. })
+        ));
+
+        let row = ".".repeat(300);
+        let layer = format!("(layers: [(y: 0, rows: [\"{row}\"])])");
+        assert!(matches!(
+            load(&layer),
+            Err(SceneFileError::RegionTooLarge { .. })
+        ));
+
+        let layer = format!("(layers: [(x: {}, y: 0, rows: [\"..\"])])", i32::MAX);
+        assert!(matches!(
+            load(&layer),
+            Err(SceneFileError::RegionOutOfBounds { .. })
+        ));
+    }
+}
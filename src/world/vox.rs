@@ -0,0 +1,434 @@
+//! MagicaVoxel `.vox` import and export.
+//!
+//! Only the first model of a file is imported, the scene graph (`nTRN`, `nGRP`, `nSHP`) and
+//! materials are ignored. MagicaVoxel is z-up, so model `(x, y, z)` becomes block
+//! `(x, z, size.y - 1 - y)`, which keeps the model from being mirrored.
+//!
+//! Palette colors are mapped to blocks by picking the nearest color in a [`VoxMapping`]. Exported
+//! files use the colors of the same mapping, so a scene survives a round trip unchanged, apart
+//! from being moved to the origin. Like voxels dropped on import, blocks that can't be exported
+//! are reported.
+
+use super::{Block, BloxScene, DEFAULT_MAX_IMPORT_SIZE, SceneFileError, binary::Reader};
+use bevy::{math::I64Vec3, prelude::*};
+
+const MAGIC: &[u8; 4] = b"VOX ";
+const VERSION: u32 = 150;
+
+/// Largest model MagicaVoxel supports.
+const MAX_MODEL_SIZE: i32 = 256;
+
+/// Maps between palette colors and blocks.
+#[derive(Debug, Clone)]
+pub struct VoxMapping {
+    entries: Vec<([u8; 3], Block)>,
+}
+
+impl Default for VoxMapping {
+    /// Roughly the average color of each block texture.
+    fn default() -> Self {
+        Self::new([
+            ([134, 96, 67], Block::DIRT),
+            ([125, 125, 125], Block::STONE),
+            ([219, 207, 163], Block::SAND),
+            ([95, 159, 53], Block::GRASS),
+            ([160, 130, 80], Block::WOOD),
+            ([48, 100, 32], Block::LEAVES),
+            ([50, 90, 200], Block::WATER),
+            ([200, 230, 240], Block::GLASS),
+            ([240, 200, 130], Block::LAMP),
+        ])
+    }
+}
+
+impl VoxMapping {
+    pub fn new(entries: impl IntoIterator<Item = ([u8; 3], Block)>) -> Self {
+        Self {
+            entries: entries.into_iter().collect(),
+        }
+    }
+
+    /// Add a color, or change the block of a color that is already mapped. Mapping a color to
+    /// [`Block::AIR`] drops voxels of that color on import.
+    pub fn with_color(mut self, color: [u8; 3], block: Block) -> Self {
+        match self.entries.iter_mut().find(|(c, _)| *c == color) {
+            Some(entry) => entry.1 = block,
+            None => self.entries.push((color, block)),
+        }
+        self
+    }
+
+    /// Block with the color closest to `color`, [`Block::AIR`] if the mapping is empty.
+    pub fn block(&self, color: [u8; 3]) -> Block {
+        let distance = |c: [u8; 3]| {
+            (0..3)
+                .map(|i| (c[i] as i32 - color[i] as i32).pow(2))
+                .sum::<i32>()
+        };
+        self.entries
+            .iter()
+            .min_by_key(|(c, _)| distance(*c))
+            .map_or(Block::AIR, |&(_, block)| block)
+    }
+
+    /// Color used when exporting `block`, the first color mapped to it.
+    pub fn color(&self, block: Block) -> Option<[u8; 3]> {
+        self.entries
+            .iter()
+            .find(|&&(_, b)| b == block)
+            .map(|&(color, _)| color)
+    }
+}
+
+/// What to do with models larger than [`VoxImportOptions::max_size`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VoxOversize {
+    /// Keep the part that fits, the rest is reported in [`VoxImport::dropped`].
+    #[default]
+    Crop,
+    /// Fail with [`SceneFileError::ModelTooLarge`].
+    Reject,
+}
+
+#[derive(Debug, Clone)]
+pub struct VoxImportOptions {
+    pub mapping: VoxMapping,
+    /// Size limit in blocks, with y up unlike the z up of `.vox` files.
+    pub max_size: UVec3,
+    pub oversize: VoxOversize,
+}
+
+impl Default for VoxImportOptions {
+    fn default() -> Self {
+        Self {
+            mapping: VoxMapping::default(),
+            max_size: DEFAULT_MAX_IMPORT_SIZE,
+            oversize: VoxOversize::default(),
+        }
+    }
+}
+
+impl VoxImportOptions {
+    pub fn with_mapping(mut self, mapping: VoxMapping) -> Self {
+        self.mapping = mapping;
+        self
+    }
+
+    pub fn with_max_size(mut self, max_size: UVec3) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn with_oversize(mut self, oversize: VoxOversize) -> Self {
+        self.oversize = oversize;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VoxImport {
+    pub scene: BloxScene,
+    pub dropped: Vec<DroppedVoxel>,
+}
+
+/// A voxel of the model that is not in the imported scene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DroppedVoxel {
+    /// Position in model coordinates.
+    pub position: UVec3,
+    pub color_index: u8,
+    pub reason: DropReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// Cropped because it is outside of [`VoxImportOptions::max_size`] on import, or more than
+    /// 256 blocks from the min corner of the scene on export.
+    OutOfBounds,
+    /// Its color is mapped to [`Block::AIR`] on import.
+    Air,
+    /// Its block has no color in the [`VoxMapping`] on export.
+    Unmapped,
+}
+
+#[derive(Debug, Clone)]
+pub struct VoxExport {
+    pub bytes: Vec<u8>,
+    pub dropped: Vec<DroppedBlock>,
+}
+
+/// A block of the scene that is not in the exported model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DroppedBlock {
+    pub position: IVec3,
+    pub block: Block,
+    pub reason: DropReason,
+}
+
+impl BloxScene {
+    pub fn from_vox(bytes: &[u8], options: &VoxImportOptions) -> Result<VoxImport, SceneFileError> {
+        let mut reader = Reader { bytes };
+        if reader.array().ok() != Some(*MAGIC) {
+            return Err(SceneFileError::InvalidMagic);
+        }
+        // Version 150 and 200 only differ in chunks we skip
+        reader.array::<4>()?;
+
+        let (id, _, children) = read_chunk(&mut reader)?;
+        if id != *b"MAIN" {
+            return Err(SceneFileError::MissingChunk("MAIN"));
+        }
+
+        let mut reader = Reader { bytes: children };
+        let mut size = None;
+        let mut voxels = None;
+        let mut palette = None;
+        while !reader.bytes.is_empty() {
+            let (id, content, _) = read_chunk(&mut reader)?;
+            let mut content = Reader { bytes: content };
+            match &id {
+                b"SIZE" if size.is_none() => {
+                    size = Some(UVec3::new(
+                        read_u32(&mut content)?,
+                        read_u32(&mut content)?,
+                        read_u32(&mut content)?,
+                    ));
+                }
+                b"XYZI" if voxels.is_none() => {
+                    let count = read_u32(&mut content)? as usize;
+                    voxels = Some(content.take(count * 4)?.chunks_exact(4));
+                }
+                b"RGBA" => {
+                    let mut colors = [[0; 3]; 256];
+                    for color in colors.iter_mut().skip(1) {
+                        let [r, g, b, _] = content.array()?;
+                        *color = [r, g, b];
+                    }
+                    palette = Some(colors);
+                }
+                _ => (),
+            }
+        }
+
+        let size = size.ok_or(SceneFileError::MissingChunk("SIZE"))?;
+        let voxels = voxels.ok_or(SceneFileError::MissingChunk("XYZI"))?;
+        let palette = palette.unwrap_or_else(|| std::array::from_fn(|i| default_color(i as u8)));
+
+        // The limit is in block space, where y is up
+        let block_size = UVec3::new(size.x, size.z, size.y);
+        if options.oversize == VoxOversize::Reject && block_size.cmpgt(options.max_size).any() {
+            return Err(SceneFileError::ModelTooLarge {
+                size: block_size,
+                max: options.max_size,
+            });
+        }
+
+        let mut scene = BloxScene::empty();
+        let mut dropped = Vec::new();
+        for voxel in voxels {
+            let [x, y, z, color_index]: [u8; 4] = voxel.try_into().unwrap();
+            let position = UVec3::new(x as u32, y as u32, z as u32);
+            let block = options.mapping.block(palette[color_index as usize]);
+            let pos = IVec3::new(x as i32, z as i32, size.y as i32 - 1 - y as i32);
+
+            let reason = match block {
+                Block::AIR => DropReason::Air,
+                _ if pos.cmplt(IVec3::ZERO).any()
+                    || pos.cmpge(options.max_size.as_ivec3()).any() =>
+                {
+                    DropReason::OutOfBounds
+                }
+                _ => {
+                    scene.set_block(pos, block);
+                    continue;
+                }
+            };
+            dropped.push(DroppedVoxel {
+                position,
+                color_index,
+                reason,
+            });
+        }
+
+        Ok(VoxImport { scene, dropped })
+    }
+
+    /// Write the scene as a single model covering [`BloxScene::bounds`]. Blocks without a color
+    /// in `mapping` and blocks more than 256 from the min corner are left out and reported in
+    /// [`VoxExport::dropped`].
+    pub fn to_vox(&self, mapping: &VoxMapping) -> VoxExport {
+        let (min, max) = self.bounds().unwrap_or_default();
+        let min = min.as_i64vec3();
+        let size = (max.as_i64vec3() - min)
+            .min(I64Vec3::splat(MAX_MODEL_SIZE as i64))
+            .as_ivec3();
+
+        // Sorted, so the same scene always gives the same file
+        let mut blocks = self.blocks().collect::<Vec<_>>();
+        blocks.sort_by_key(|&(pos, _)| (pos.x, pos.y, pos.z));
+
+        let mut palette = Vec::<(Block, [u8; 3])>::new();
+        let mut voxels = Vec::new();
+        let mut dropped = Vec::new();
+        for (position, block) in blocks {
+            let mut drop = |reason| {
+                dropped.push(DroppedBlock {
+                    position,
+                    block,
+                    reason,
+                })
+            };
+            let offset = position.as_i64vec3() - min;
+            if offset.cmpge(size.as_i64vec3()).any() {
+                drop(DropReason::OutOfBounds);
+                continue;
+            }
+            let [x, y, z] = offset.as_ivec3().to_array();
+            let index = match palette.iter().position(|&(b, _)| b == block) {
+                Some(index) => index,
+                None => {
+                    let Some(color) = mapping.color(block) else {
+                        drop(DropReason::Unmapped);
+                        continue;
+                    };
+                    palette.push((block, color));
+                    palette.len() - 1
+                }
+            };
+            voxels.extend_from_slice(&[x as u8, (size.z - 1 - z) as u8, y as u8]);
+            voxels.push(index as u8 + 1);
+        }
+
+        let mut children = Vec::new();
+
+        let mut content = Vec::new();
+        for value in [size.x, size.z, size.y] {
+            content.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        write_chunk(&mut children, b"SIZE", &content, &[]);
+
+        let mut content = Vec::new();
+        content.extend_from_slice(&(voxels.len() as u32 / 4).to_le_bytes());
+        content.extend_from_slice(&voxels);
+        write_chunk(&mut children, b"XYZI", &content, &[]);
+
+        // Entry i is the color of index i + 1, unused entries are the default palette
+        let content = (1..=256)
+            .flat_map(|i| {
+                let [r, g, b] = palette
+                    .get(i - 1)
+                    .map_or_else(|| default_color(i as u8), |&(_, color)| color);
+                [r, g, b, 255]
+            })
+            .collect::<Vec<_>>();
+        write_chunk(&mut children, b"RGBA", &content, &[]);
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        write_chunk(&mut bytes, b"MAIN", &[], &children);
+        VoxExport { bytes, dropped }
+    }
+}
+
+fn read_u32(reader: &mut Reader) -> Result<u32, SceneFileError> {
+    Ok(u32::from_le_bytes(reader.array()?))
+}
+
+/// Returns the chunk id, its content and its children.
+fn read_chunk<'a>(
+    reader: &mut Reader<'a>,
+) -> Result<([u8; 4], &'a [u8], &'a [u8]), SceneFileError> {
+    let id = reader.array()?;
+    let content_size = read_u32(reader)? as usize;
+    let children_size = read_u32(reader)? as usize;
+    let content = reader.take(content_size)?;
+    let children = reader.take(children_size)?;
+    Ok((id, content, children))
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(children.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(content);
+    bytes.extend_from_slice(children);
+}
+
+/// Color of the MagicaVoxel default palette, used by files without an `RGBA` chunk. Indices 1 to
+/// 215 are a color cube without black, followed by red, green, blue and gray ramps.
+fn default_color(index: u8) -> [u8; 3] {
+    const RAMP: [u8; 10] = [0xEE, 0xDD, 0xBB, 0xAA, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let index = index as usize;
+    match index {
+        0 => [0, 0, 0],
+        1..=215 => {
+            let i = index - 1;
+            let level = |step: usize| 0xFF - 0x33 * step as u8;
+            [level(i / 36), level(i / 6 % 6), level(i % 6)]
+        }
+        _ => {
+            let i = index - 216;
+            let value = RAMP[i % 10];
+            match i / 10 {
+                0 => [value, 0, 0],
+                1 => [0, value, 0],
+                2 => [0, 0, value],
+                _ => [value, value, value],
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_limit_is_in_block_space() {
+        // A tower, tall along y in blocks and along z in the file
+        let mut scene = BloxScene::empty();
+        for y in 0..3 {
+            scene.set_block(IVec3::new(0, y, 0), Block::STONE);
+        }
+        let bytes = scene.to_vox(&VoxMapping::default()).bytes;
+        let options = |max_size| {
+            VoxImportOptions::default()
+                .with_max_size(max_size)
+                .with_oversize(VoxOversize::Reject)
+        };
+
+        let import = BloxScene::from_vox(&bytes, &options(UVec3::new(1, 3, 1))).unwrap();
+        assert!(import.dropped.is_empty());
+        assert_eq!(import.scene, scene);
+
+        let err = BloxScene::from_vox(&bytes, &options(UVec3::new(1, 1, 3))).unwrap_err();
+        assert!(matches!(
+            err,
+            SceneFileError::ModelTooLarge { size, .. } if size == UVec3::new(1, 3, 1)
+        ));
+    }
+
+    #[test]
+    fn blocks_that_cant_be_exported_are_reported() {
+        let unmapped = Block::from_id(200);
+        let mut scene = BloxScene::empty();
+        scene.set_block(IVec3::ZERO, Block::STONE);
+        scene.set_block(IVec3::new(1, 0, 0), unmapped);
+        scene.set_block(IVec3::new(300, 0, 0), Block::STONE);
+
+        let export = scene.to_vox(&VoxMapping::default());
+        let reasons = export
+            .dropped
+            .iter()
+            .map(|dropped| (dropped.position.x, dropped.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            [(1, DropReason::Unmapped), (300, DropReason::OutOfBounds)]
+        );
+
+        let import = BloxScene::from_vox(&export.bytes, &VoxImportOptions::default()).unwrap();
+        assert_eq!(import.scene.blocks().count(), 1);
+    }
+}